@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+
+use crate::g_code_instruction::Plane;
+
+/// Difference between the radius at the start and at the end of an arc, in mm and relative to
+/// the radius, that is put down to rounding in the program.
+const RADIUS_TOLERANCE: f32 = 0.002;
+const RELATIVE_RADIUS_TOLERANCE: f32 = 0.001;
+/// Smallest angle between interpolated points, which bounds the number of chords of arcs with
+/// a radius far larger than the chord tolerance.
+const MIN_STEP: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArcCenter {
+    Offset(f32, f32, f32),
+    Radius(f32),
+}
+
+/// Whether an arc from `start` to `end` exists: an R word must reach half the chord and with
+/// I, J and K the end must lie on the circle around the centre, up to rounding.
+pub fn is_valid_arc(
+    start: (f32, f32, f32),
+    end: (f32, f32, f32),
+    center: ArcCenter,
+    plane: Plane,
+) -> bool {
+    let (start_a, start_b, _) = to_plane(start, plane);
+    let (end_a, end_b, _) = to_plane(end, plane);
+
+    let (radius, end_radius) = match center {
+        ArcCenter::Offset(i, j, k) => {
+            let (offset_a, offset_b, _) = to_plane((i, j, k), plane);
+            let (center_a, center_b) = (start_a + offset_a, start_b + offset_b);
+            (
+                (offset_a.powi(2) + offset_b.powi(2)).sqrt(),
+                ((end_a - center_a).powi(2) + (end_b - center_b).powi(2)).sqrt(),
+            )
+        }
+        ArcCenter::Radius(r) => {
+            let half_chord = ((end_a - start_a).powi(2) + (end_b - start_b).powi(2)).sqrt() / 2.0;
+            (r.abs(), r.abs().max(half_chord))
+        }
+    };
+    (radius - end_radius).abs() <= RADIUS_TOLERANCE.max(RELATIVE_RADIUS_TOLERANCE * radius)
+}
+
+/// Points along the arc, the last one being `end`. `chord_tolerance` (mm) must be positive.
+pub fn interpolate_arc(
+    start: (f32, f32, f32),
+    end: (f32, f32, f32),
+    center: ArcCenter,
+    clockwise: bool,
+    plane: Plane,
+    chord_tolerance: f32,
+) -> Vec<(f32, f32, f32)> {
+    let (start_a, start_b, start_l) = to_plane(start, plane);
+    let (end_a, end_b, end_l) = to_plane(end, plane);
+
+    let (center_a, center_b) = match center {
+        ArcCenter::Offset(i, j, k) => {
+            let (offset_a, offset_b, _) = to_plane((i, j, k), plane);
+            (start_a + offset_a, start_b + offset_b)
+        }
+        ArcCenter::Radius(r) => {
+            let chord = (end_a - start_a, end_b - start_b);
+            let chord_length = (chord.0.powi(2) + chord.1.powi(2)).sqrt();
+            if chord_length == 0.0 {
+                return vec![end];
+            }
+
+            let h = (r.powi(2) - (chord_length / 2.0).powi(2)).max(0.0).sqrt();
+            let right = (chord.1 / chord_length, -chord.0 / chord_length);
+            let side = if clockwise == (r > 0.0) { 1.0 } else { -1.0 };
+            (
+                (start_a + end_a) / 2.0 + side * h * right.0,
+                (start_b + end_b) / 2.0 + side * h * right.1,
+            )
+        }
+    };
+
+    let radius = ((start_a - center_a).powi(2) + (start_b - center_b).powi(2)).sqrt();
+    if radius == 0.0 {
+        return vec![end];
+    }
+
+    let start_angle = (start_b - center_b).atan2(start_a - center_a);
+    let end_angle = (end_b - center_b).atan2(end_a - center_a);
+    let mut sweep = end_angle - start_angle;
+    if clockwise && sweep >= 0.0 {
+        sweep -= 2.0 * PI;
+    } else if !clockwise && sweep <= 0.0 {
+        sweep += 2.0 * PI;
+    }
+
+    // 2 * acos(1 - tolerance / radius), without rounding to zero for large radii.
+    let max_step = (2.0 * (2.0 * chord_tolerance / radius).sqrt()).clamp(MIN_STEP, PI / 2.0);
+    let segments = (sweep.abs() / max_step).ceil().max(1.0) as usize;
+
+    (1..=segments)
+        .map(|i| {
+            if i == segments {
+                return end;
+            }
+            let t = i as f32 / segments as f32;
+            let angle = start_angle + sweep * t;
+            from_plane(
+                (
+                    center_a + radius * angle.cos(),
+                    center_b + radius * angle.sin(),
+                    start_l + (end_l - start_l) * t,
+                ),
+                plane,
+            )
+        })
+        .collect()
+}
+
+fn to_plane(point: (f32, f32, f32), plane: Plane) -> (f32, f32, f32) {
+    match plane {
+        Plane::XY => (point.0, point.1, point.2),
+        Plane::ZX => (point.2, point.0, point.1),
+        Plane::YZ => (point.1, point.2, point.0),
+    }
+}
+
+fn from_plane(point: (f32, f32, f32), plane: Plane) -> (f32, f32, f32) {
+    match plane {
+        Plane::XY => (point.0, point.1, point.2),
+        Plane::ZX => (point.1, point.2, point.0),
+        Plane::YZ => (point.2, point.0, point.1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{interpolate_arc, is_valid_arc, ArcCenter};
+    use crate::g_code_instruction::Plane;
+
+    fn assert_close(a: (f32, f32, f32), b: (f32, f32, f32)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4 && (a.2 - b.2).abs() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[rstest]
+    #[case(ArcCenter::Offset(0.0, -10.0, 0.0), true, (0.0, -10.0))]
+    #[case(ArcCenter::Radius(10.0), true, (0.0, -10.0))]
+    #[case(ArcCenter::Offset(10.0, 0.0, 0.0), false, (10.0, 0.0))]
+    #[case(ArcCenter::Radius(10.0), false, (10.0, 0.0))]
+    #[case(ArcCenter::Radius(-10.0), true, (10.0, 0.0))]
+    fn arc_points_lie_on_circle(
+        #[case] center: ArcCenter,
+        #[case] clockwise: bool,
+        #[case] expected_center: (f32, f32),
+    ) {
+        let end = (10.0, -10.0, 0.0);
+
+        let points = interpolate_arc((0.0, 0.0, 0.0), end, center, clockwise, Plane::XY, 0.001);
+
+        assert_close(*points.last().unwrap(), end);
+        for point in points {
+            let radius = ((point.0 - expected_center.0).powi(2)
+                + (point.1 - expected_center.1).powi(2))
+            .sqrt();
+            assert!((radius - 10.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn full_circle_is_interpolated_when_start_equals_end() {
+        let points = interpolate_arc(
+            (5.0, 0.0, 0.0),
+            (5.0, 0.0, 0.0),
+            ArcCenter::Offset(-5.0, 0.0, 0.0),
+            false,
+            Plane::XY,
+            0.01,
+        );
+
+        assert!(points.len() > 4);
+        assert!(points.iter().any(|p| (p.0 + 5.0).abs() < 0.1));
+    }
+
+    #[test]
+    fn chord_tolerance_limits_segment_count() {
+        let coarse = interpolate_arc(
+            (10.0, 0.0, 0.0),
+            (-10.0, 0.0, 0.0),
+            ArcCenter::Offset(-10.0, 0.0, 0.0),
+            false,
+            Plane::XY,
+            1.0,
+        );
+        let fine = interpolate_arc(
+            (10.0, 0.0, 0.0),
+            (-10.0, 0.0, 0.0),
+            ArcCenter::Offset(-10.0, 0.0, 0.0),
+            false,
+            Plane::XY,
+            0.01,
+        );
+
+        assert!(coarse.len() < fine.len());
+    }
+
+    #[test]
+    fn helical_arc_interpolates_linear_axis() {
+        let points = interpolate_arc(
+            (0.0, 0.0, 10.0),
+            (0.0, 0.0, 0.0),
+            ArcCenter::Offset(0.0, 0.0, 5.0),
+            true,
+            Plane::ZX,
+            0.01,
+        );
+
+        assert_close(*points.last().unwrap(), (0.0, 0.0, 0.0));
+        assert!(points.iter().all(|p| p.1 == 0.0));
+        assert!(points.iter().any(|p| (p.0 + 5.0).abs() < 0.1));
+    }
+
+    #[rstest]
+    #[case(ArcCenter::Radius(10.0), true)]
+    #[case(ArcCenter::Radius(-10.0), true)]
+    #[case(ArcCenter::Radius(7.072), true)]
+    #[case(ArcCenter::Radius(5.0), false)]
+    #[case(ArcCenter::Offset(0.0, -10.0, 0.0), true)]
+    #[case(ArcCenter::Offset(0.0, -10.001, 0.0), true)]
+    #[case(ArcCenter::Offset(0.0, -8.0, 0.0), false)]
+    fn arc_radii_must_match(#[case] center: ArcCenter, #[case] valid: bool) {
+        assert_eq!(
+            is_valid_arc((0.0, 0.0, 0.0), (10.0, -10.0, 0.0), center, Plane::XY),
+            valid
+        );
+    }
+
+    #[test]
+    fn large_radius_keeps_segment_count_bounded() {
+        let radius = 20_000.0;
+
+        let points = interpolate_arc(
+            (radius, 0.0, 0.0),
+            (radius, 0.0, 0.0),
+            ArcCenter::Offset(-radius, 0.0, 0.0),
+            false,
+            Plane::XY,
+            0.001,
+        );
+
+        assert!(points.len() > 1000);
+        assert!(points.len() <= (2.0 * std::f32::consts::PI / 0.001).ceil() as usize);
+    }
+}
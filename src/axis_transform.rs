@@ -0,0 +1,127 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Maps machine coordinates (mm, Z along the tool) to scene coordinates (cm, y up).
+///
+/// Scene axis `i` takes machine axis `axes[i].0` multiplied by `axes[i].1`. The height map
+/// is vertical along scene y, so machine Z always maps to scene y without a flip; X and Y can
+/// be swapped and flipped freely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisTransform {
+    axes: [(usize, f32); 3],
+}
+
+impl Default for AxisTransform {
+    fn default() -> Self {
+        Self {
+            axes: [(1, 1.0), (2, 1.0), (0, 1.0)],
+        }
+    }
+}
+
+impl AxisTransform {
+    /// Transform with scene x taking `scene_x` and scene z taking `scene_z`, each given as
+    /// a machine axis index (0 for X, 1 for Y) and a sign.
+    pub fn new(scene_x: (usize, f32), scene_z: (usize, f32)) -> Result<Self, String> {
+        for (axis, sign) in [scene_x, scene_z] {
+            if axis > 1 {
+                return Err("only machine X and Y can map to horizontal scene axes".to_string());
+            }
+            if sign.abs() != 1.0 {
+                return Err("axis sign must be 1 or -1".to_string());
+            }
+        }
+        if scene_x.0 == scene_z.0 {
+            return Err("machine axis is mapped twice".to_string());
+        }
+
+        Ok(Self {
+            axes: [scene_x, (2, 1.0), scene_z],
+        })
+    }
+
+    pub fn scene_x(&self) -> (usize, f32) {
+        self.axes[0]
+    }
+
+    pub fn scene_z(&self) -> (usize, f32) {
+        self.axes[2]
+    }
+
+    /// Reorders and flips a machine position (or direction) keeping millimetres.
+    pub fn apply(&self, machine: (f32, f32, f32)) -> (f32, f32, f32) {
+        let machine = [machine.0, machine.1, machine.2];
+        let axis = |i: usize| machine[self.axes[i].0] * self.axes[i].1;
+        (axis(0), axis(1), axis(2))
+    }
+
+    pub fn to_scene(&self, machine_position: (f32, f32, f32)) -> (f32, f32, f32) {
+        let position = self.apply(machine_position);
+        (position.0 / 10.0, position.1 / 10.0, position.2 / 10.0)
+    }
+
+    pub fn to_machine(&self, scene_position: (f32, f32, f32)) -> (f32, f32, f32) {
+        let scene = [scene_position.0, scene_position.1, scene_position.2];
+        let mut machine = [0.0; 3];
+        for (i, (axis, sign)) in self.axes.iter().enumerate() {
+            machine[*axis] = scene[i] * sign * 10.0;
+        }
+        (machine[0], machine[1], machine[2])
+    }
+}
+
+impl Display for AxisTransform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = |(axis, sign): (usize, f32)| {
+            format!(
+                "{}{}",
+                if sign < 0.0 { "-" } else { "" },
+                ["X", "Y", "Z"][axis]
+            )
+        };
+        write!(
+            f,
+            "x = {}, y = Z, z = {}",
+            name(self.axes[0]),
+            name(self.axes[2])
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::AxisTransform;
+
+    #[test]
+    fn default_transform_keeps_original_mapping() {
+        let transform = AxisTransform::default();
+
+        assert_eq!(transform.to_scene((10.0, 20.0, 30.0)), (2.0, 3.0, 1.0));
+    }
+
+    #[rstest]
+    #[case((0, 1.0), (1, -1.0))]
+    #[case((1, -1.0), (0, -1.0))]
+    #[case((1, 1.0), (0, 1.0))]
+    fn to_machine_inverts_to_scene(#[case] scene_x: (usize, f32), #[case] scene_z: (usize, f32)) {
+        let transform = AxisTransform::new(scene_x, scene_z).unwrap();
+        let machine = (12.0, -34.0, 56.0);
+
+        let scene = transform.to_scene(machine);
+        let back = transform.to_machine(scene);
+
+        assert!((scene.1 - 5.6).abs() < 1e-5);
+        assert!((back.0 - machine.0).abs() < 1e-4);
+        assert!((back.1 - machine.1).abs() < 1e-4);
+        assert!((back.2 - machine.2).abs() < 1e-4);
+    }
+
+    #[rstest]
+    #[case((0, 1.0), (0, -1.0))]
+    #[case((2, 1.0), (0, 1.0))]
+    #[case((0, 2.0), (1, 1.0))]
+    fn invalid_transform_is_rejected(#[case] scene_x: (usize, f32), #[case] scene_z: (usize, f32)) {
+        assert!(AxisTransform::new(scene_x, scene_z).is_err());
+    }
+}
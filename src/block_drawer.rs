@@ -2,10 +2,11 @@ use glium::glutin::surface::WindowSurface;
 use glium::{uniform, Display, DrawParameters, Frame, Program, Surface, Texture2d, VertexBuffer};
 use nalgebra::{Matrix4, Vector3};
 
-use crate::vertex::Vertex;
+use crate::{target_height_map::ToleranceBands, vertex::Vertex};
 
 pub struct BlockDrawer {
     program: Program,
+    fixture_program: Program,
 }
 
 impl BlockDrawer {
@@ -65,6 +66,7 @@ impl BlockDrawer {
             in vec2 out_tex_coords;
 
             uniform sampler2D target_height_map;
+            uniform sampler2D highlight_map;
 
             out vec4 frag_color;
 
@@ -73,6 +75,8 @@ impl BlockDrawer {
 
             uniform vec3 cam_pos;
             uniform bool use_target_height_map;
+            uniform float gouge_tolerance;
+            uniform float leftover_tolerance;
 
             void main() {
                 vec3 to_cam = normalize(cam_pos - world);
@@ -87,24 +91,29 @@ impl BlockDrawer {
                 if (use_target_height_map) {
                     float height = texture(target_height_map, vec2(out_tex_coords.y, out_tex_coords.x)).x;
                     if (world.y < height) {
-                        float diff = (height - world.y) * 10.0;
+                        float diff = (height - world.y) / gouge_tolerance;
                         if (diff > 1.0) {
                             color = vec3(0.8, 0.2, 0.2);
                         } else {
                             color = diff * vec3(0.8, 0.2, 0.2) + (1.0 - diff) * vec3(0.2, 0.8, 0.2);
                         }
                     } else if (world.y > height) {
-                        float diff = (world.y - height) * 10.0;
+                        float diff = (world.y - height) / leftover_tolerance;
                         if (diff > 1.0) {
                             color = vec3(0.2, 0.2, 0.8);
                         } else {
                             color = diff * vec3(0.2, 0.2, 0.8) + (1.0 - diff) * vec3(0.2, 0.8, 0.2);
                         }
-                    } else {
+                    } else if (world.y == height) {
+                        // NaN marks columns without a target, which keep the default color.
                         color = vec3(0.2, 0.8, 0.2);
                     }
                 }
 
+                if (texture(highlight_map, out_tex_coords).x > 0.5) {
+                    color = vec3(1.0, 0.5, 0.0);
+                }
+
                 frag_color = vec4((ambient + diffuse + specular) * color, 1.0);
             }
         "#;
@@ -112,9 +121,79 @@ impl BlockDrawer {
         let program =
             Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap();
 
-        Self { program }
+        let fixture_vertex_shader_src = r#"
+            #version 410 core
+
+            in vec3 position;
+            in int normal;
+
+            out vec3 normal_out;
+            out vec3 world;
+
+            uniform mat4 perspective;
+            uniform mat4 view;
+
+            void main() {
+                world = position;
+                gl_Position = perspective * view * vec4(world, 1.0);
+
+                if (normal == 0) {
+                    normal_out = vec3(0.0, 1.0, 0.0);
+                } else if (normal == 1) {
+                    normal_out = vec3(0.0, -1.0, 0.0);
+                } else if (normal == 2) {
+                    normal_out = vec3(1.0, 0.0, 0.0);
+                } else if (normal == 3) {
+                    normal_out = vec3(-1.0, 0.0, 0.0);
+                } else if (normal == 4) {
+                    normal_out = vec3(0.0, 0.0, 1.0);
+                } else if (normal == 5) {
+                    normal_out = vec3(0.0, 0.0, -1.0);
+                }
+            }
+        "#;
+
+        let fixture_fragment_shader_src = r#"
+            #version 410 core
+
+            in vec3 normal_out;
+            in vec3 world;
+
+            out vec4 frag_color;
+
+            const vec3 light_pos = vec3(7.0, 25.0, -7.0);
+            const vec3 color = vec3(0.3, 0.35, 0.6);
+
+            uniform vec3 cam_pos;
+
+            void main() {
+                vec3 to_cam = normalize(cam_pos - world);
+                vec3 to_light = normalize(light_pos - world);
+
+                float ambient = 0.3;
+                float diffuse =  max(dot(normal_out, to_light), 0.0);
+                vec3 reflected = normalize(reflect(-to_light, normal_out));
+                float specular = pow(max(dot(reflected, to_cam), 0.0), 50.0);
+
+                frag_color = vec4((ambient + diffuse + specular) * color, 1.0);
+            }
+        "#;
+
+        let fixture_program = Program::from_source(
+            display,
+            fixture_vertex_shader_src,
+            fixture_fragment_shader_src,
+            None,
+        )
+        .unwrap();
+
+        Self {
+            program,
+            fixture_program,
+        }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &self,
         target: &mut Frame,
@@ -126,6 +205,8 @@ impl BlockDrawer {
         height_map: &Texture2d,
         target_height_map: &Texture2d,
         use_target_height_map: bool,
+        tolerance_bands: &ToleranceBands,
+        highlight_map: &Texture2d,
     ) {
         let index_buffer = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
@@ -145,8 +226,39 @@ impl BlockDrawer {
                         .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
                         .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
                         use_target_height_map: use_target_height_map,
+                        gouge_tolerance: tolerance_bands.gouge() / 10.0,
+                        leftover_tolerance: tolerance_bands.leftover() / 10.0,
+                        highlight_map: highlight_map.sampled()
+                        .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+                        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+                },
+                drawing_parameters,
+            )
+            .unwrap();
+    }
+
+    pub fn draw_fixtures(
+        &self,
+        target: &mut Frame,
+        vertex_buffer: &VertexBuffer<Vertex>,
+        perspective: &Matrix4<f32>,
+        view_matrix: &Matrix4<f32>,
+        drawing_parameters: &DrawParameters,
+        camera_position: Vector3<f32>,
+    ) {
+        let index_buffer = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+        target
+            .draw(
+                vertex_buffer,
+                index_buffer,
+                &self.fixture_program,
+                &uniform! {
+                    perspective: perspective.data.0,
+                    view: view_matrix.data.0,
+                    cam_pos: camera_position.data.0[0],
                 },
-                &drawing_parameters,
+                drawing_parameters,
             )
             .unwrap();
     }
@@ -0,0 +1,212 @@
+use derive_getters::Getters;
+use serde::Serialize;
+
+use crate::target_height_map::{TargetHeightMap, ToleranceBands};
+
+/// Deviation of the milled stock from the target. Lengths are in mm, volumes in mm³. Gouges are
+/// columns cut below the target, leftovers columns left above it.
+#[derive(Debug, Clone, Copy, PartialEq, Getters, Serialize)]
+pub struct ComparisonReport {
+    #[getter(copy)]
+    tolerance_bands: ToleranceBands,
+    #[getter(copy)]
+    max_gouge: f32,
+    /// Mean depth of the columns cut deeper than the gouge tolerance.
+    #[getter(copy)]
+    mean_gouge: f32,
+    #[getter(copy)]
+    max_leftover: f32,
+    /// Mean excess of the columns left higher than the leftover tolerance.
+    #[getter(copy)]
+    mean_leftover: f32,
+    #[getter(copy)]
+    rms_deviation: f32,
+    /// Material removed from the stock the height map started with.
+    #[getter(copy)]
+    removed_volume: f32,
+    #[getter(copy)]
+    remaining_volume: f32,
+    #[getter(copy)]
+    gouge_volume: f32,
+    #[getter(copy)]
+    leftover_volume: f32,
+    /// Share of the columns with a target that are within the tolerance bands, in percent.
+    #[getter(copy)]
+    within_tolerance: f32,
+}
+
+impl ComparisonReport {
+    /// Compares the height map columns (scene cm, indexed by x and then z) with `target`, which
+    /// is sampled as the block shader does. Columns without a target count only towards the
+    /// volumes. `stock_heights` are the columns before milling, e.g. of an imported stock.
+    pub fn new(
+        heights: &[Vec<f32>],
+        stock_heights: &[Vec<f32>],
+        target: &TargetHeightMap,
+        block_size: (f32, f32, f32),
+        tolerance_bands: ToleranceBands,
+    ) -> Self {
+        let resolution = (heights.len(), heights[0].len());
+        let cell_area =
+            block_size.0 * 10.0 / resolution.0 as f32 * block_size.2 * 10.0 / resolution.1 as f32;
+        let bottom = -block_size.1 * 10.0 / 2.0;
+        let normalized = |index: usize, count: usize| index as f32 / (count.max(2) - 1) as f32;
+
+        let mut report = Self {
+            tolerance_bands,
+            max_gouge: 0.0,
+            mean_gouge: 0.0,
+            max_leftover: 0.0,
+            mean_leftover: 0.0,
+            rms_deviation: 0.0,
+            removed_volume: 0.0,
+            remaining_volume: 0.0,
+            gouge_volume: 0.0,
+            leftover_volume: 0.0,
+            within_tolerance: 0.0,
+        };
+        let (mut compared, mut gouges, mut leftovers, mut within) =
+            (0usize, 0usize, 0usize, 0usize);
+        let (mut squared_sum, mut gouge_sum, mut leftover_sum) = (0.0, 0.0, 0.0);
+
+        for (i, column) in heights.iter().enumerate() {
+            for (k, height) in column.iter().enumerate() {
+                let height = height * 10.0;
+                let target_height =
+                    target.height_at(normalized(i, resolution.0), normalized(k, resolution.1));
+                report.removed_volume += (stock_heights[i][k] * 10.0 - height) * cell_area;
+                report.remaining_volume += (height - bottom) * cell_area;
+                if target_height.is_nan() {
+                    continue;
+                }
+
+                let deviation = height - target_height;
+                compared += 1;
+                squared_sum += deviation * deviation;
+                if deviation < -tolerance_bands.gouge() {
+                    gouges += 1;
+                    gouge_sum -= deviation;
+                } else if deviation > tolerance_bands.leftover() {
+                    leftovers += 1;
+                    leftover_sum += deviation;
+                } else {
+                    within += 1;
+                }
+                if deviation < 0.0 {
+                    report.max_gouge = report.max_gouge.max(-deviation);
+                    report.gouge_volume -= deviation * cell_area;
+                } else if deviation > 0.0 {
+                    report.max_leftover = report.max_leftover.max(deviation);
+                    report.leftover_volume += deviation * cell_area;
+                }
+            }
+        }
+
+        let count = compared.max(1) as f32;
+        report.mean_gouge = gouge_sum / gouges.max(1) as f32;
+        report.mean_leftover = leftover_sum / leftovers.max(1) as f32;
+        report.rms_deviation = (squared_sum / count).sqrt();
+        report.within_tolerance = within as f32 / count * 100.0;
+        report
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// One `metric,value` row per field.
+    pub fn to_csv(&self) -> String {
+        let rows = [
+            ("gouge_tolerance", self.tolerance_bands.gouge()),
+            ("leftover_tolerance", self.tolerance_bands.leftover()),
+            ("max_gouge", self.max_gouge),
+            ("mean_gouge", self.mean_gouge),
+            ("max_leftover", self.max_leftover),
+            ("mean_leftover", self.mean_leftover),
+            ("rms_deviation", self.rms_deviation),
+            ("removed_volume", self.removed_volume),
+            ("remaining_volume", self.remaining_volume),
+            ("gouge_volume", self.gouge_volume),
+            ("leftover_volume", self.leftover_volume),
+            ("within_tolerance", self.within_tolerance),
+        ];
+        let mut csv = "metric,value\n".to_string();
+        for (metric, value) in rows {
+            csv.push_str(&format!("{},{}\n", metric, value));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ComparisonReport;
+    use crate::target_height_map::{TargetHeightMap, ToleranceBands};
+
+    const BLOCK_SIZE: (f32, f32, f32) = (2.0, 2.0, 2.0);
+
+    fn report() -> ComparisonReport {
+        // Target 5 mm above the block centre on an 18 mm high stock, one gouged and one left
+        // over column outside the tolerance bands.
+        let target =
+            TargetHeightMap::from_scene_heights(&[vec![0.5, 0.5], vec![0.5, 0.5]], BLOCK_SIZE);
+        let heights = [vec![0.5, 0.3], vec![0.52, 0.6]];
+        let stock_heights = [vec![0.8, 0.8], vec![0.8, 0.8]];
+        ComparisonReport::new(
+            &heights,
+            &stock_heights,
+            &target,
+            BLOCK_SIZE,
+            ToleranceBands::new(0.5, 0.5),
+        )
+    }
+
+    #[test]
+    fn deviations_are_measured() {
+        let report = report();
+
+        assert!((report.max_gouge() - 2.0).abs() < 1e-4);
+        assert!((report.mean_gouge() - 2.0).abs() < 1e-4);
+        assert!((report.max_leftover() - 1.0).abs() < 1e-4);
+        assert!((report.mean_leftover() - 1.0).abs() < 1e-4);
+        assert!((report.rms_deviation() - (5.04f32 / 4.0).sqrt()).abs() < 1e-4);
+        assert_eq!(report.within_tolerance(), 50.0);
+    }
+
+    #[test]
+    fn volumes_are_measured() {
+        let report = report();
+
+        // Each column is 10 mm by 10 mm, cut from 18 mm high stock.
+        assert!((report.remaining_volume() - 100.0 * (15.0 + 13.0 + 15.2 + 16.0)).abs() < 1e-2);
+        assert!((report.removed_volume() + report.remaining_volume() - 7200.0).abs() < 1e-2);
+        assert!((report.gouge_volume() - 200.0).abs() < 1e-2);
+        assert!((report.leftover_volume() - 120.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn csv_lists_every_metric() {
+        let csv = report().to_csv();
+
+        assert_eq!(csv.lines().next(), Some("metric,value"));
+        assert_eq!(csv.lines().count(), 13);
+        assert!(csv.contains("within_tolerance,50\n"));
+    }
+
+    #[test]
+    fn columns_without_target_are_not_compared() {
+        let target = TargetHeightMap::from_scene_heights(&[vec![0.5, f32::NAN]], (2.0, 2.0, 2.0));
+
+        let report = ComparisonReport::new(
+            &[vec![0.5, -1.0]],
+            &[vec![1.0, 1.0]],
+            &target,
+            (2.0, 2.0, 2.0),
+            ToleranceBands::default(),
+        );
+
+        assert_eq!(report.within_tolerance(), 100.0);
+        assert_eq!(report.max_gouge(), 0.0);
+        assert!((report.removed_volume() - 200.0 * (5.0 + 20.0)).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::Debug;
+
+use derive_getters::Getters;
+use derive_new::new;
+use serde::{Deserialize, Serialize};
+
+/// Cylinder clamping the tool, starting where the tool sticks out (`overall_length`).
+#[derive(Debug, Clone, Copy, PartialEq, Getters, Serialize, Deserialize, new)]
+pub struct ToolHolder {
+    #[getter(copy)]
+    diameter: f32,
+    #[getter(copy)]
+    length: f32,
+}
+
+/// Geometry of a rotating tool as seen by the simulator. All lengths are in millimetres
+/// and measured from the tool tip.
+pub trait CutterProfile: Debug {
+    /// Radius of the cutting part of the tool.
+    fn radius(&self) -> f32;
+
+    /// Height of the cutting edge above the tip at the given distance from the tool axis.
+    fn height_at(&self, offset: f32) -> f32;
+
+    /// Whether `height_at` is non-decreasing and convex, which lets the swept-volume update
+    /// search for the lowest point directly instead of sampling the profile first.
+    fn is_convex(&self) -> bool {
+        false
+    }
+
+    /// Radius of the bottom region that cannot cut when the tool moves straight down.
+    /// Zero for tools that can plunge.
+    fn non_cutting_radius(&self) -> f32;
+
+    /// Length of the fluted part of the tool, `None` when unknown.
+    fn cutting_length(&self) -> Option<f32>;
+
+    /// Total length sticking out of the holder, `None` when unknown.
+    fn overall_length(&self) -> Option<f32>;
+
+    /// Radius of the non-cutting shank above the flutes.
+    fn shank_radius(&self) -> f32 {
+        self.radius()
+    }
+
+    /// Holder above the tool, `None` when it is not modelled.
+    fn holder(&self) -> Option<ToolHolder> {
+        None
+    }
+}
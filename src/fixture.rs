@@ -0,0 +1,187 @@
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+
+use crate::cutter_profile::CutterProfile;
+
+const SEARCH_ITERATIONS: usize = 32;
+
+/// Clamp or fixture approximated by a box in machine coordinates (mm).
+#[derive(Debug, Clone, PartialEq, Getters, Serialize, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    name: String,
+    #[getter(copy)]
+    min: (f32, f32, f32),
+    #[getter(copy)]
+    max: (f32, f32, f32),
+}
+
+#[derive(Debug, Clone, Default, Getters, Serialize, Deserialize)]
+pub struct FixtureSet {
+    fixtures: Vec<Fixture>,
+}
+
+impl Fixture {
+    pub fn new(name: String, min: (f32, f32, f32), max: (f32, f32, f32)) -> Self {
+        Self {
+            name,
+            min: (min.0.min(max.0), min.1.min(max.1), min.2.min(max.2)),
+            max: (min.0.max(max.0), min.1.max(max.1), min.2.max(max.2)),
+        }
+    }
+
+    /// Checks whether any part of the tool overlaps the fixture while its tip moves in a
+    /// straight line from `start` to `end` (machine coordinates, mm). Parts of unknown length
+    /// reach up to the spindle.
+    pub fn collides(
+        &self,
+        start: (f32, f32, f32),
+        end: (f32, f32, f32),
+        cutter_profile: &dyn CutterProfile,
+    ) -> bool {
+        let cutting_length = cutter_profile.cutting_length();
+        let overall_length = cutter_profile.overall_length();
+
+        let mut parts = vec![(
+            cutter_profile.radius(),
+            0.0,
+            cutting_length.or(overall_length).unwrap_or(f32::INFINITY),
+        )];
+        if let Some(cutting_length) = cutting_length {
+            parts.push((
+                cutter_profile.shank_radius(),
+                cutting_length,
+                overall_length.unwrap_or(f32::INFINITY),
+            ));
+        }
+        if let (Some(overall_length), Some(holder)) = (overall_length, cutter_profile.holder()) {
+            parts.push((
+                holder.diameter() / 2.0,
+                overall_length,
+                overall_length + holder.length(),
+            ));
+        }
+
+        let horizontal_distance = |t: f32| {
+            let x = start.0 + (end.0 - start.0) * t;
+            let y = start.1 + (end.1 - start.1) * t;
+            ((x - x.clamp(self.min.0, self.max.0)).powi(2)
+                + (y - y.clamp(self.min.1, self.max.1)).powi(2))
+            .sqrt()
+        };
+
+        parts.into_iter().any(|(radius, bottom, top)| {
+            // Part of the move during which the tool part is level with the fixture.
+            let Some((first, last)) = below(start.2 + bottom, end.2 + bottom, self.max.2)
+                .zip(below(-(start.2 + top), -(end.2 + top), -self.min.2))
+                .map(|((a, b), (c, d))| (a.max(c), b.min(d)))
+                .filter(|(first, last)| first <= last)
+            else {
+                return false;
+            };
+
+            // The distance to the box is convex along the move.
+            let (mut low, mut high) = (first, last);
+            for _ in 0..SEARCH_ITERATIONS {
+                let left = low + (high - low) / 3.0;
+                let right = high - (high - low) / 3.0;
+                if horizontal_distance(left) <= horizontal_distance(right) {
+                    high = right;
+                } else {
+                    low = left;
+                }
+            }
+            horizontal_distance((low + high) / 2.0) < radius
+        })
+    }
+}
+
+/// Part of `0..=1` where the value going linearly from `from` to `to` is below `limit`.
+fn below(from: f32, to: f32, limit: f32) -> Option<(f32, f32)> {
+    match (from < limit, to < limit) {
+        (true, true) => Some((0.0, 1.0)),
+        (false, false) => None,
+        (true, false) => Some((0.0, (limit - from) / (to - from))),
+        (false, true) => Some(((limit - from) / (to - from), 1.0)),
+    }
+}
+
+impl FixtureSet {
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let set: FixtureSet = serde_json::from_str(content).map_err(|error| error.to_string())?;
+        Ok(Self {
+            fixtures: set
+                .fixtures
+                .into_iter()
+                .map(|fixture| Fixture::new(fixture.name, fixture.min, fixture.max))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{Fixture, FixtureSet};
+    use crate::{
+        cutter_profile::ToolHolder,
+        milling_cutter::{CutterShape, MillingCutter},
+    };
+
+    fn clamp() -> Fixture {
+        Fixture::new("clamp".to_string(), (40.0, -10.0, 50.0), (60.0, 10.0, 60.0))
+    }
+
+    #[rstest]
+    #[case((50.0, 0.0, 55.0), true)]
+    #[case((50.0, 0.0, 70.0), false)]
+    #[case((50.0, 0.0, 20.0), true)]
+    #[case((66.0, 0.0, 55.0), false)]
+    #[case((64.0, 0.0, 55.0), true)]
+    fn cutter_with_unknown_length_collides(#[case] tip: (f32, f32, f32), #[case] collides: bool) {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, None, None);
+
+        assert_eq!(clamp().collides(tip, tip, &cutter), collides);
+    }
+
+    #[rstest]
+    #[case((50.0, 0.0, -60.0), false)]
+    #[case((50.0, 0.0, 0.0), true)]
+    #[case((75.0, 0.0, 15.0), true)]
+    #[case((80.0, 0.0, 15.0), false)]
+    fn holder_collides(#[case] tip: (f32, f32, f32), #[case] collides: bool) {
+        let cutter = MillingCutter::new(CutterShape::Flat, 6.0, Some(20.0), Some(40.0))
+            .with_holder(ToolHolder::new(40.0, 50.0));
+
+        assert_eq!(clamp().collides(tip, tip, &cutter), collides);
+    }
+
+    #[rstest]
+    #[case((20.0, 0.0, 55.0), (80.0, 0.0, 55.0), true)]
+    #[case((50.0, -30.0, 55.0), (50.0, 30.0, 55.0), true)]
+    #[case((20.0, 20.0, 55.0), (80.0, 20.0, 55.0), false)]
+    #[case((20.0, 0.0, 80.0), (80.0, 0.0, 75.0), false)]
+    #[case((40.0, 0.0, 100.0), (60.0, 0.0, 0.0), true)]
+    #[case((60.0, 0.0, -50.0), (60.0, 0.0, -40.0), false)]
+    fn move_through_fixture_collides(
+        #[case] start: (f32, f32, f32),
+        #[case] end: (f32, f32, f32),
+        #[case] collides: bool,
+    ) {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, Some(20.0), Some(40.0));
+
+        assert_eq!(clamp().collides(start, end, &cutter), collides);
+    }
+
+    #[test]
+    fn fixtures_are_parsed() {
+        let set = FixtureSet::from_json(
+            r#"{ "fixtures": [ { "name": "left", "min": [10, 0, 50], "max": [0, 20, 60] } ] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(set.fixtures()[0].min(), (0.0, 0.0, 50.0));
+        assert_eq!(set.fixtures()[0].max(), (10.0, 20.0, 60.0));
+    }
+}
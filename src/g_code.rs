@@ -1,27 +1,547 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
 use derive_getters::Getters;
+use derive_new::new;
 
-use crate::{g_code_instruction::GCodeInstruction, milling_cutter::MillingCutter};
+use crate::{
+    g_code_instruction::{
+        split_comments, GCodeInstruction, MotionMode, ParseError, ParseErrorKind,
+    },
+    machine_state::MachineState,
+    milling_cutter::MillingCutter,
+    tool_library::{Tool, ToolLibrary},
+};
 
 #[derive(Debug, Clone, Getters)]
 pub struct GCode {
     instructions: Vec<GCodeInstruction>,
     cutter: MillingCutter,
+    #[getter(copy)]
+    tool_number: Option<u32>,
+    tools: BTreeMap<u32, Tool>,
+    #[getter(copy)]
+    program_number: Option<u32>,
+    /// Words that were read but have no effect on the simulation.
+    warnings: Vec<LineError>,
+}
+
+#[derive(Debug, Clone, Getters, new)]
+pub struct LineError {
+    #[getter(copy)]
+    line: usize,
+    error: ParseError,
+}
+
+#[derive(Debug)]
+pub enum GCodeError {
+    Io(std::io::Error),
+    MissingCutter,
+    InvalidCutter(String),
+    UnknownTool(u32),
+    InvalidLines(Vec<LineError>),
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line,
+            self.error.column(),
+            self.error.kind()
+        )
+    }
+}
+
+impl Display for GCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GCodeError::Io(error) => write!(f, "cannot read file: {}", error),
+            GCodeError::MissingCutter => write!(
+                f,
+                "no cutter definition (header comment, sidecar file or file extension)"
+            ),
+            GCodeError::InvalidCutter(reason) => write!(f, "invalid cutter definition: {}", reason),
+            GCodeError::UnknownTool(number) => {
+                write!(f, "tool T{} is not in the tool library", number)
+            }
+            GCodeError::InvalidLines(errors) => {
+                write!(f, "{} invalid line(s)", errors.len())
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for GCodeError {
+    fn from(error: std::io::Error) -> Self {
+        GCodeError::Io(error)
+    }
 }
 
 impl GCode {
-    pub fn from_file(file_path: &str) -> Option<Self> {
-        let dot_position = file_path.find(".")?;
-        let file_extension = &file_path[(dot_position + 1)..];
-        let cutter = MillingCutter::parse(file_extension)?;
-
-        let content = std::fs::read_to_string(file_path).unwrap();
-        let instructions = content
-            .split_whitespace()
-            .filter_map(|line| GCodeInstruction::parse(line))
-            .collect();
-        Some(Self {
+    /// Cutter precedence: `cutter_override`, the first T word looked up in `tool_library`,
+    /// a `CUTTER` header comment, a `<file>.cutter.json` sidecar file and finally the file
+    /// extension convention (`.k16`, `.f10`).
+    pub fn from_file(
+        file_path: &str,
+        skip_block_delete: bool,
+        cutter_override: Option<MillingCutter>,
+        tool_library: Option<&ToolLibrary>,
+    ) -> Result<Self, GCodeError> {
+        let content = std::fs::read_to_string(file_path)?;
+
+        let fallback_cutter = match cutter_override {
+            Some(_) => None,
+            None => Self::sidecar_cutter(file_path)?.or_else(|| {
+                Path::new(file_path)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .and_then(MillingCutter::parse)
+            }),
+        };
+
+        Self::parse(
+            &content,
+            cutter_override,
+            fallback_cutter,
+            skip_block_delete,
+            tool_library,
+        )
+    }
+
+    fn sidecar_cutter(file_path: &str) -> Result<Option<MillingCutter>, GCodeError> {
+        let sidecar_path = format!("{}.cutter.json", file_path);
+        if !Path::new(&sidecar_path).exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(sidecar_path)?;
+        let cutter: MillingCutter = serde_json::from_str(&content)
+            .map_err(|error| GCodeError::InvalidCutter(error.to_string()))?;
+        cutter.validate().map_err(GCodeError::InvalidCutter)?;
+        Ok(Some(cutter))
+    }
+
+    /// Parses a program. The cutter is `cutter_override` if given, otherwise chosen as in
+    /// [`GCode::from_file`] with `fallback_cutter` standing for the sidecar file and extension.
+    pub fn parse(
+        content: &str,
+        cutter_override: Option<MillingCutter>,
+        fallback_cutter: Option<MillingCutter>,
+        skip_block_delete: bool,
+        tool_library: Option<&ToolLibrary>,
+    ) -> Result<Self, GCodeError> {
+        let mut instructions = Vec::new();
+        let mut program_number = None;
+        let mut pending_comments = Vec::new();
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        let lines = content.lines().enumerate().collect::<Vec<_>>();
+        let mut delimiters = lines
+            .iter()
+            .filter(|(_, line)| line.trim_start().starts_with('%'))
+            .map(|(index, _)| *index);
+        let program_range = match delimiters.next() {
+            Some(start) => (start + 1)..delimiters.next().unwrap_or(lines.len()),
+            None => 0..lines.len(),
+        };
+
+        for (index, line) in lines[program_range].iter() {
+            let (code, comments) = match split_comments(line) {
+                Ok(split) => split,
+                Err(error) => {
+                    errors.push(LineError::new(index + 1, error));
+                    continue;
+                }
+            };
+
+            let trimmed = code.trim_start();
+            let code = match trimmed.chars().next() {
+                None => {
+                    pending_comments.extend(comments);
+                    continue;
+                }
+                Some('/') if skip_block_delete => continue,
+                Some('/') => code.replacen('/', " ", 1),
+                Some('O') | Some('o') => {
+                    let column = code.len() - trimmed.len() + 1;
+                    let value = trimmed[1..].trim();
+                    match value.parse::<u32>() {
+                        Ok(number) => program_number = Some(number),
+                        Err(_) => errors.push(LineError::new(
+                            index + 1,
+                            ParseError::new(
+                                column,
+                                ParseErrorKind::InvalidValue('O', value.to_string()),
+                            ),
+                        )),
+                    }
+                    pending_comments.extend(comments);
+                    continue;
+                }
+                Some(_) => code,
+            };
+
+            match GCodeInstruction::parse(&code) {
+                Ok(instruction) => {
+                    warnings.extend(
+                        instruction
+                            .ignored_words()
+                            .iter()
+                            .map(|word| LineError::new(index + 1, word.clone())),
+                    );
+                    let mut instruction_comments = std::mem::take(&mut pending_comments);
+                    instruction_comments.extend(comments);
+                    instructions.push(
+                        instruction
+                            .with_comments(instruction_comments)
+                            .with_line(index + 1),
+                    );
+                }
+                Err(error) => errors.push(LineError::new(index + 1, error)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(GCodeError::InvalidLines(errors));
+        }
+
+        if let Some(last) = instructions.pop() {
+            instructions.push(last.with_comments(pending_comments));
+        }
+
+        let mut tools = BTreeMap::new();
+        if let Some(tool_library) = tool_library {
+            for number in instructions
+                .iter()
+                .filter_map(|instruction| instruction.t())
+            {
+                let tool = tool_library
+                    .get(number)
+                    .ok_or(GCodeError::UnknownTool(number))?;
+                tools.insert(number, tool.clone());
+            }
+        }
+
+        // Only a tool selected before the first cutting move is in the spindle from the start.
+        let mut tool_number = None;
+        let mut motion_mode = MotionMode::Rapid;
+        for instruction in instructions.iter() {
+            if let Some(number) = instruction.t() {
+                tool_number = Some(number);
+                break;
+            }
+            motion_mode = instruction.motion_mode().unwrap_or(motion_mode);
+            if instruction.has_axis_words() && motion_mode != MotionMode::Rapid {
+                break;
+            }
+        }
+        let library_cutter = tool_number
+            .and_then(|number| tools.get(&number))
+            .map(|tool| tool.cutter().clone());
+
+        let cutter = match cutter_override {
+            Some(cutter) => {
+                cutter.validate().map_err(GCodeError::InvalidCutter)?;
+                cutter
+            }
+            None => {
+                let header_cutter = instructions
+                    .iter()
+                    .flat_map(|instruction| instruction.comments())
+                    .find_map(|comment| MillingCutter::from_comment(comment))
+                    .transpose()
+                    .map_err(GCodeError::InvalidCutter)?;
+                library_cutter
+                    .or(header_cutter)
+                    .or(fallback_cutter)
+                    .ok_or(GCodeError::MissingCutter)?
+            }
+        };
+
+        Ok(Self {
             instructions,
             cutter,
+            tool_number,
+            tools,
+            program_number,
+            warnings,
         })
     }
+
+    /// Content hash of the parsed instructions, which tells programs apart also when they have
+    /// no O word. Uses FNV-1a so that the value is the same in every build.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for instruction in self.instructions.iter() {
+            for byte in format!("{:?}", instruction).bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Points the tool passes through in machine coordinates, starting from `machine_state`.
+    pub fn path(
+        &self,
+        mut machine_state: MachineState,
+        chord_tolerance: f32,
+    ) -> Vec<(f32, f32, f32)> {
+        let mut path = vec![machine_state.position()];
+
+        for instruction in self.instructions.iter() {
+            path.extend(machine_state.apply(instruction, chord_tolerance));
+        }
+
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{GCode, GCodeError};
+    use crate::{
+        g_code_instruction::ParseErrorKind,
+        machine_state::MachineState,
+        milling_cutter::{CutterShape, MillingCutter},
+        tool_library::ToolLibrary,
+    };
+
+    fn flat_cutter() -> MillingCutter {
+        MillingCutter::new(CutterShape::Flat, 10.0, None, None)
+    }
+
+    #[test]
+    fn every_invalid_line_is_reported() {
+        let content = "N1G01X1\n\nN2G01X1.2.3\nN3G01Y2\nN4#1\n";
+
+        let result = GCode::parse(content, None, Some(flat_cutter()), false, None);
+
+        let Err(GCodeError::InvalidLines(errors)) = result else {
+            panic!("expected invalid lines");
+        };
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line(), 3);
+        assert_eq!(errors[0].error().column(), 6);
+        assert_eq!(errors[1].line(), 5);
+        assert_eq!(
+            errors[1].error().kind(),
+            &ParseErrorKind::UnexpectedCharacter('#')
+        );
+    }
+
+    #[test]
+    fn continuation_lines_and_ignored_words_are_accepted() {
+        let content = "N1G01X1F500\nN2X5Y3\nN3G04P1\nN4G43H1Z50\n";
+
+        let g_code = GCode::parse(content, None, Some(flat_cutter()), false, None).unwrap();
+
+        assert_eq!(g_code.instructions().len(), 4);
+        assert_eq!(
+            g_code.path(MachineState::new((0.0, 0.0, 0.0)), 0.01)[2],
+            (5.0, 3.0, 0.0)
+        );
+        let warnings = g_code
+            .warnings()
+            .iter()
+            .map(|warning| (warning.line(), warning.error().kind().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (3, ParseErrorKind::IgnoredWord('P')),
+                (4, ParseErrorKind::IgnoredWord('H'))
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let g_code = GCode::parse(
+            "N1G01X1\n   \nN2G01Y1\n",
+            None,
+            Some(flat_cutter()),
+            false,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(g_code.instructions().len(), 2);
+    }
+
+    #[test]
+    fn comments_are_attached_to_instructions() {
+        let content = "(ROUGHING X10)\nN1G01X1 ; plunge\nN2G01Y2\n(END)\n";
+
+        let g_code = GCode::parse(content, None, Some(flat_cutter()), false, None).unwrap();
+
+        assert_eq!(g_code.instructions().len(), 2);
+        assert_eq!(g_code.instructions()[0].x(), Some(1.0));
+        assert_eq!(
+            g_code.instructions()[0].comments(),
+            &vec!["ROUGHING X10".to_string(), "plunge".to_string()]
+        );
+        assert_eq!(
+            g_code.instructions()[1].comments(),
+            &vec!["END".to_string()]
+        );
+    }
+
+    #[test]
+    fn blocks_without_line_numbers_are_labelled_by_line() {
+        let content = "(FACING)\nG00X0Y0Z50\nN20G01Z40F100\nX10\n";
+
+        let g_code = GCode::parse(content, None, Some(flat_cutter()), false, None).unwrap();
+
+        let labels = g_code
+            .instructions()
+            .iter()
+            .map(|instruction| instruction.label())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["line 2", "N20", "line 4"]);
+    }
+
+    #[test]
+    fn only_lines_between_percent_markers_are_read() {
+        let content = "header X1\n%\nO1234 (PART)\nN1G01X1\n%\ntrailer";
+
+        let g_code = GCode::parse(content, None, Some(flat_cutter()), false, None).unwrap();
+
+        assert_eq!(g_code.program_number(), Some(1234));
+        assert_eq!(g_code.instructions().len(), 1);
+    }
+
+    #[rstest]
+    #[case(true, 1)]
+    #[case(false, 2)]
+    fn block_delete_lines_are_optionally_skipped(
+        #[case] skip_block_delete: bool,
+        #[case] instruction_count: usize,
+    ) {
+        let content = "N1G01X1\n/N2G01X2\n";
+
+        let g_code =
+            GCode::parse(content, None, Some(flat_cutter()), skip_block_delete, None).unwrap();
+
+        assert_eq!(g_code.instructions().len(), instruction_count);
+    }
+
+    #[test]
+    fn header_comment_overrides_fallback_cutter() {
+        let content = "(CUTTER type=ball diameter=8.5 cutting_length=20)\nN1G01X1\n";
+
+        let g_code = GCode::parse(content, None, Some(flat_cutter()), false, None).unwrap();
+
+        assert_eq!(
+            g_code.cutter(),
+            &MillingCutter::new(CutterShape::Spherical, 8.5, Some(20.0), None)
+        );
+    }
+
+    #[test]
+    fn missing_cutter_is_reported() {
+        let result = GCode::parse("N1G01X1\n", None, None, false, None);
+
+        assert!(matches!(result, Err(GCodeError::MissingCutter)));
+    }
+
+    #[test]
+    fn invalid_header_cutter_is_reported() {
+        let result = GCode::parse("(CUTTER type=flat)\nN1G01X1\n", None, None, false, None);
+
+        assert!(matches!(result, Err(GCodeError::InvalidCutter(_))));
+    }
+
+    #[test]
+    fn invalid_header_cutter_is_ignored_with_override() {
+        let g_code = GCode::parse(
+            "(CUTTER type=flat)\nN1G01X1\n",
+            Some(flat_cutter()),
+            None,
+            false,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(g_code.cutter(), &flat_cutter());
+    }
+
+    #[test]
+    fn t_word_selects_cutter_from_tool_library() {
+        let library = ToolLibrary::from_json(
+            r#"{ "tools": [{ "number": 3, "shape": "Spherical", "diameter": 12.0 }] }"#,
+        )
+        .unwrap();
+        let content = "(CUTTER type=flat diameter=4)\nN1T3M06\nN2G01X1\n";
+
+        let g_code = GCode::parse(content, None, None, false, Some(&library)).unwrap();
+
+        assert_eq!(g_code.tool_number(), Some(3));
+        assert!(g_code.tools().contains_key(&3));
+        assert_eq!(
+            g_code.cutter(),
+            &MillingCutter::new(CutterShape::Spherical, 12.0, None, None)
+        );
+    }
+
+    #[test]
+    fn t_word_after_first_cut_does_not_select_initial_cutter() {
+        let library = ToolLibrary::from_json(
+            r#"{ "tools": [{ "number": 3, "shape": "Spherical", "diameter": 12.0 }] }"#,
+        )
+        .unwrap();
+        let content = "(CUTTER type=flat diameter=4)\nN1G00X1\nN2G01X2\nN3T3M06\nN4G01X3\n";
+
+        let g_code = GCode::parse(content, None, None, false, Some(&library)).unwrap();
+
+        assert_eq!(g_code.tool_number(), None);
+        assert!(g_code.tools().contains_key(&3));
+        assert_eq!(
+            g_code.cutter(),
+            &MillingCutter::new(CutterShape::Flat, 4.0, None, None)
+        );
+    }
+
+    #[test]
+    fn every_referenced_tool_is_resolved() {
+        let library = ToolLibrary::from_json(
+            r#"{ "tools": [
+                { "number": 1, "shape": "Flat", "diameter": 16.0 },
+                { "number": 2, "shape": "Spherical", "diameter": 8.0 },
+                { "number": 5, "shape": "Spherical", "diameter": 2.0 }
+            ] }"#,
+        )
+        .unwrap();
+        let content = "N1T1M06\nN2G01X1\nN3T2M06\nN4G01X2\n";
+
+        let g_code = GCode::parse(content, None, None, false, Some(&library)).unwrap();
+
+        assert_eq!(
+            g_code.tools().keys().copied().collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn unknown_tool_is_reported() {
+        let library = ToolLibrary::default();
+
+        let result = GCode::parse(
+            "N1T3M06\n",
+            None,
+            Some(flat_cutter()),
+            false,
+            Some(&library),
+        );
+
+        assert!(matches!(result, Err(GCodeError::UnknownTool(3))));
+    }
 }
@@ -1,33 +1,203 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
 use derive_getters::Getters;
 use derive_new::new;
-use line_drawing::Bresenham3d;
 
-use crate::{g_code::GCode, height_map::HeightMap, milling_cutter::MillingCutter};
+use crate::{
+    cutter_profile::CutterProfile,
+    g_code::GCode,
+    height_map::HeightData,
+    machine_setup::MachineSetup,
+    machine_state::MachineState,
+    swept_volume::{sweep, ColumnGrid, Cylinder},
+    time_estimate::TimeEstimate,
+};
+
+pub const START_POSITION: (f32, f32, f32) = (0.0, 0.0, 220.0);
 
 #[derive(Debug, Clone, Getters)]
 pub struct GCodeExecutor {
     current_instruction: usize,
     current_position: (f32, f32, f32),
-    cutter: Vec<CutterPart>,
+    /// Where the tool is drawn, part way along the next step while it is being animated.
+    #[getter(copy)]
+    tool_position: (f32, f32, f32),
+    cutter_profile: Rc<dyn CutterProfile>,
     code: GCode,
     resolution: (u32, u32, u32),
     size: (f32, f32, f32),
-    current_points: Option<Vec<(i32, i32, i32)>>,
+    current_points: Option<Vec<(f32, f32, f32)>>,
     current_point: Option<usize>,
     error: Option<ExecutionError>,
-    resolution_heights: Vec<f32>,
+    warnings: Vec<ExecutionError>,
+    rapid_collisions: Vec<RapidCollision>,
+    error_policy: ErrorPolicy,
+    #[getter(copy)]
+    remove_material_on_rapid: bool,
+    machine_setup: MachineSetup,
+    machine_state: MachineState,
+    #[getter(copy)]
+    chord_tolerance: f32,
+    #[getter(copy)]
+    limit_height_by_resolution: bool,
+    #[getter(copy)]
+    active_tool: Option<u32>,
+    #[getter(skip)]
+    current_move_is_plunge: bool,
+    #[getter(skip)]
+    current_move_is_rapid: bool,
+    /// Machine time in seconds spent on the current program.
+    #[getter(copy)]
+    elapsed_time: f32,
+    /// Machine time in seconds the whole current program takes.
+    #[getter(copy)]
+    program_time: f32,
+    /// Machine time in seconds of every instruction, from the [`TimeEstimate`] of the program.
+    #[getter(skip)]
+    instruction_times: Vec<f32>,
+    /// Length of the current move in scene units, used to share its time between the steps.
+    #[getter(skip)]
+    current_move_length: f32,
+    #[getter(skip)]
+    time_budget: f32,
+    #[getter(skip)]
+    program_start_state: MachineState,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionErrorKind {
+    RapidIntoMaterial,
+    PlungeWithFlatCutter,
+    BelowStockBottom,
+    ExceededImmersion,
+    OutOfBounds,
+    ShankCollision,
+    HolderCollision,
+    BelowSafetyFloor,
+    FixtureCollision,
+    FeedAboveToolLimit,
+    ArcRadiusMismatch,
+}
+
+/// A problem found while milling. `position` is where it was found in machine coordinates (mm):
+/// the contact point for problems with the material, otherwise the tool tip.
 #[derive(Debug, Clone, Getters, new)]
-pub struct CutterPart {
-    index_offset: (usize, usize),
-    position_offset: (f32, f32, f32),
+pub struct ExecutionError {
+    #[getter(copy)]
+    instruction: usize,
+    /// Label of the block, see [`crate::g_code_instruction::GCodeInstruction::label`].
+    block: String,
+    #[getter(copy)]
+    position: (f32, f32, f32),
+    #[getter(copy)]
+    kind: ExecutionErrorKind,
+}
+
+/// A rapid move that touched remaining material. `contact` is the deepest contact point in
+/// machine coordinates and `depth` how far below the surface the tool went there (mm).
+#[derive(Debug, Clone, Getters, new)]
+pub struct RapidCollision {
+    #[getter(copy)]
+    instruction: usize,
+    block: String,
+    #[getter(copy)]
+    contact: (f32, f32, f32),
+    #[getter(copy)]
+    depth: f32,
+}
+
+/// Decides which kinds of problems stop the simulation. The other ones are only recorded as
+/// warnings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorPolicy {
+    stopping: Vec<ExecutionErrorKind>,
+}
+
+impl ExecutionErrorKind {
+    pub const ALL: [ExecutionErrorKind; 11] = [
+        ExecutionErrorKind::RapidIntoMaterial,
+        ExecutionErrorKind::PlungeWithFlatCutter,
+        ExecutionErrorKind::BelowStockBottom,
+        ExecutionErrorKind::ExceededImmersion,
+        ExecutionErrorKind::OutOfBounds,
+        ExecutionErrorKind::ShankCollision,
+        ExecutionErrorKind::HolderCollision,
+        ExecutionErrorKind::BelowSafetyFloor,
+        ExecutionErrorKind::FixtureCollision,
+        ExecutionErrorKind::FeedAboveToolLimit,
+        ExecutionErrorKind::ArcRadiusMismatch,
+    ];
+}
+
+impl Display for ExecutionErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionErrorKind::RapidIntoMaterial => write!(f, "Rapid move into material"),
+            ExecutionErrorKind::PlungeWithFlatCutter => write!(f, "Plunge with flat cutter"),
+            ExecutionErrorKind::BelowStockBottom => write!(f, "Cut below stock bottom"),
+            ExecutionErrorKind::ExceededImmersion => write!(f, "Too deep cutter immersion"),
+            ExecutionErrorKind::OutOfBounds => write!(f, "Move out of bounds"),
+            ExecutionErrorKind::ShankCollision => write!(f, "Shank collision"),
+            ExecutionErrorKind::HolderCollision => write!(f, "Holder collision"),
+            ExecutionErrorKind::BelowSafetyFloor => write!(f, "Tool below base plate"),
+            ExecutionErrorKind::FixtureCollision => write!(f, "Fixture collision"),
+            ExecutionErrorKind::FeedAboveToolLimit => write!(f, "Feed above tool limit"),
+            ExecutionErrorKind::ArcRadiusMismatch => write!(f, "Arc radius mismatch"),
+        }
+    }
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at X{:.3} Y{:.3} Z{:.3}: {}",
+            self.block, self.position.0, self.position.1, self.position.2, self.kind
+        )
+    }
 }
 
-#[derive(Debug, Clone, new)]
-pub enum ExecutionError {
-    TooDeepImmersion,
-    VerticalCut,
+impl Display for RapidCollision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rapid collision at X{:.3} Y{:.3} Z{:.3}, depth {:.3} mm",
+            self.block, self.contact.0, self.contact.1, self.contact.2, self.depth
+        )
+    }
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        Self {
+            stopping: vec![
+                ExecutionErrorKind::PlungeWithFlatCutter,
+                ExecutionErrorKind::BelowStockBottom,
+                ExecutionErrorKind::ExceededImmersion,
+                ExecutionErrorKind::ShankCollision,
+                ExecutionErrorKind::HolderCollision,
+                ExecutionErrorKind::BelowSafetyFloor,
+                ExecutionErrorKind::FixtureCollision,
+                ExecutionErrorKind::ArcRadiusMismatch,
+            ],
+        }
+    }
+}
+
+impl ErrorPolicy {
+    pub fn stops(&self, kind: ExecutionErrorKind) -> bool {
+        self.stopping.contains(&kind)
+    }
+
+    pub fn set_stops(&mut self, kind: ExecutionErrorKind, stops: bool) {
+        self.stopping.retain(|&k| k != kind);
+        if stops {
+            self.stopping.push(kind);
+        }
+    }
 }
 
 impl GCodeExecutor {
@@ -37,210 +207,524 @@ impl GCodeExecutor {
         size: (f32, f32, f32),
         limit_height_by_resolution: bool,
     ) -> Self {
-        let resolution_heights = (0..=resolution.1)
-            .map(|i| i as f32 * size.1 / (resolution.1 as f32) - size.1 / 2.0)
-            .collect::<Vec<f32>>();
+        let cutter_profile: Rc<dyn CutterProfile> = Rc::new(code.cutter().clone());
+        let active_tool = code.tool_number();
+        let machine_setup = MachineSetup::default();
 
-        let cutter = Self::get_cutter(
-            &code,
-            resolution,
-            size,
-            if limit_height_by_resolution {
-                Some(&resolution_heights)
-            } else {
-                None
-            },
-        );
+        let home = machine_setup
+            .axis_transform()
+            .to_scene(machine_setup.home_position());
 
-        Self {
-            current_position: (0.0, 22.0, 0.0),
+        let mut executor = Self {
+            current_position: home,
+            tool_position: home,
             current_instruction: 0,
-            code,
-            cutter,
+            cutter_profile,
             resolution,
             size,
             current_points: None,
             current_point: None,
             error: None,
-            resolution_heights,
-        }
+            warnings: vec![],
+            rapid_collisions: vec![],
+            error_policy: ErrorPolicy::default(),
+            remove_material_on_rapid: false,
+            machine_state: machine_setup.initial_state(),
+            program_start_state: machine_setup.initial_state(),
+            machine_setup,
+            chord_tolerance: 0.01,
+            limit_height_by_resolution,
+            active_tool,
+            current_move_is_plunge: false,
+            current_move_is_rapid: false,
+            elapsed_time: 0.0,
+            program_time: 0.0,
+            instruction_times: vec![],
+            current_move_length: 0.0,
+            time_budget: 0.0,
+            code,
+        };
+        executor.update_program_time();
+        executor
     }
 
     pub fn load(&mut self, code: GCode, limit_height_by_resolution: bool) {
         self.current_instruction = 0;
-        self.cutter = Self::get_cutter(
-            &code,
-            self.resolution,
-            self.size,
-            if limit_height_by_resolution {
-                Some(&self.resolution_heights)
-            } else {
-                None
-            },
-        );
+        self.limit_height_by_resolution = limit_height_by_resolution;
+        self.set_cutter_profile(Rc::new(code.cutter().clone()));
+        self.active_tool = code.tool_number();
         self.code = code;
         self.current_point = None;
         self.current_points = None;
+        self.error = None;
+        self.warnings.clear();
+        self.rapid_collisions.clear();
+        self.machine_state = MachineState::new(self.machine_state.position())
+            .with_home(self.machine_state.home())
+            .with_work_offsets(*self.machine_state.work_offsets());
+        self.program_start_state = self.machine_state.clone();
+        self.elapsed_time = 0.0;
+        self.time_budget = 0.0;
+        self.update_program_time();
     }
 
-    fn get_cutter(
-        code: &GCode,
-        resolution: (u32, u32, u32),
-        size: (f32, f32, f32),
-        resolution_heights: Option<&Vec<f32>>,
-    ) -> Vec<CutterPart> {
-        let cutter_size = match code.cutter() {
-            MillingCutter::Flat(size) => *size,
-            MillingCutter::Spherical(size) => *size,
-        } as f32
-            / 20.0;
-
-        let single_size: (f32, f32) = (
-            size.0 / (resolution.0 as f32),
-            size.2 / (resolution.2 as f32),
+    /// Skips the first `instruction` instructions without cutting, e.g. to continue a run on a
+    /// saved stock. Has no effect once the program has started or when `instruction` is past
+    /// its end.
+    pub fn resume_from(&mut self, instruction: usize) {
+        if self.current_instruction != 0
+            || self.current_points.is_some()
+            || instruction > self.code.instructions().len()
+        {
+            return;
+        }
+
+        for index in 0..instruction {
+            let skipped = self.code.instructions()[index].clone();
+            self.machine_state.apply(&skipped, self.chord_tolerance);
+            if skipped.has_m(6) {
+                self.change_tool();
+            }
+        }
+        self.current_instruction = instruction;
+        self.current_position = self.to_scene(self.machine_state.position());
+        self.tool_position = self.current_position;
+        self.elapsed_time = self.instruction_times[..instruction].iter().sum();
+    }
+
+    /// Applies the home position, work offsets, base plate, fixtures and axis mapping. Before
+    /// the first step the tool is also moved to the new home position. A move in progress
+    /// finishes with the old axis mapping.
+    pub fn set_machine_setup(&mut self, machine_setup: MachineSetup) {
+        let not_started = self.current_instruction == 0 && self.current_points.is_none();
+        self.machine_state = if not_started {
+            machine_setup.initial_state()
+        } else {
+            self.machine_state
+                .clone()
+                .with_home(machine_setup.home_position())
+                .with_work_offsets(*machine_setup.work_offsets())
+        };
+        self.machine_setup = machine_setup;
+        if not_started {
+            self.program_start_state = self.machine_state.clone();
+        } else {
+            self.program_start_state = self
+                .program_start_state
+                .clone()
+                .with_home(self.machine_setup.home_position())
+                .with_work_offsets(*self.machine_setup.work_offsets());
+        }
+        self.update_program_time();
+        if self.current_points.is_none() {
+            self.current_position = self.to_scene(self.machine_state.position());
+            self.tool_position = self.current_position;
+        }
+    }
+
+    pub fn set_cutter_profile(&mut self, cutter_profile: Rc<dyn CutterProfile>) {
+        self.cutter_profile = cutter_profile;
+    }
+
+    fn change_tool(&mut self) {
+        let Some(number) = self.machine_state.tool() else {
+            return;
+        };
+        if let Some(tool) = self.code.tools().get(&number) {
+            let cutter_profile = Rc::new(tool.cutter().clone());
+            self.set_cutter_profile(cutter_profile);
+            self.active_tool = Some(number);
+        }
+    }
+
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    /// When set, rapid moves cut the material they touch and the cut is highlighted. Otherwise
+    /// the material is left untouched and only the collision is recorded.
+    pub fn set_remove_material_on_rapid(&mut self, remove_material_on_rapid: bool) {
+        self.remove_material_on_rapid = remove_material_on_rapid;
+    }
+
+    /// Largest distance in mm between an arc and the chords it is milled along.
+    pub fn set_chord_tolerance(&mut self, chord_tolerance: f32) -> Result<(), String> {
+        if !(chord_tolerance.is_finite() && chord_tolerance > 0.0) {
+            return Err("chord tolerance must be positive".to_string());
+        }
+        self.chord_tolerance = chord_tolerance;
+        self.update_program_time();
+        Ok(())
+    }
+
+    pub fn to_scene(&self, machine_position: (f32, f32, f32)) -> (f32, f32, f32) {
+        self.machine_setup
+            .axis_transform()
+            .to_scene(machine_position)
+    }
+
+    pub fn to_machine(&self, scene_position: (f32, f32, f32)) -> (f32, f32, f32) {
+        self.machine_setup
+            .axis_transform()
+            .to_machine(scene_position)
+    }
+
+    /// Records `kind` found at `position` (scene coordinates) for the current instruction, once
+    /// per instruction, and stops the execution if the policy says so. Only the first stopping
+    /// problem becomes the error, later ones are kept with the warnings.
+    fn report(&mut self, kind: ExecutionErrorKind, position: (f32, f32, f32)) {
+        let error = ExecutionError::new(
+            self.current_instruction,
+            self.code.instructions()[self.current_instruction].label(),
+            self.to_machine(position),
+            kind,
         );
+        if self.error_policy.stops(kind) && self.error.is_none() {
+            self.error = Some(error);
+        } else if !self
+            .warnings
+            .iter()
+            .any(|w| w.instruction == error.instruction && w.kind == kind)
+        {
+            self.warnings.push(error);
+        }
+    }
 
-        let cutter_space = (
-            (cutter_size / single_size.0) as usize,
-            (cutter_size / single_size.1) as usize,
+    /// Keeps the deepest contact of every rapid instruction.
+    fn record_rapid_collision(&mut self, contact: (f32, f32, f32), depth: f32) {
+        let collision = RapidCollision::new(
+            self.current_instruction,
+            self.code.instructions()[self.current_instruction].label(),
+            contact,
+            depth,
         );
+        match self.rapid_collisions.last_mut() {
+            Some(last) if last.instruction == collision.instruction => {
+                if collision.depth > last.depth {
+                    *last = collision;
+                }
+            }
+            _ => self.rapid_collisions.push(collision),
+        }
+    }
 
-        (0..=cutter_space.0)
-            .flat_map(|x| (0..=cutter_space.1).map(move |z| (x, z)))
-            .filter(|&(x, z)| {
-                let x_offset = single_size.0 * x as f32;
-                let z_offset = single_size.1 * z as f32;
-                let distance = (x_offset.powi(2) + z_offset.powi(2)).sqrt();
-                distance <= cutter_size
-            })
-            .map(|(x, z)| {
-                let x_offset = single_size.0 * x as f32;
-                let z_offset = single_size.1 * z as f32;
-                let y_offset = match code.cutter() {
-                    MillingCutter::Flat(_) => 0.0,
-                    MillingCutter::Spherical(_) => {
-                        let h = cutter_size
-                            - (cutter_size.powi(2) - x_offset.powi(2) - z_offset.powi(2))
-                                .max(0.0)
-                                .sqrt();
-
-                        if let Some(resolution_heights) = resolution_heights {
-                            resolution_heights
-                                .iter()
-                                .cloned()
-                                .min_by(|h1, h2| {
-                                    let d1 = (h - h1).abs();
-                                    let d2 = (h - h2).abs();
-                                    d1.partial_cmp(&d2).unwrap()
-                                })
-                                .unwrap()
-                        } else {
-                            h
-                        }
-                    }
-                };
+    pub fn execution_finished(&self) -> bool {
+        self.current_instruction >= self.code.instructions().len() || self.error.is_some()
+    }
+
+    fn snap_height(&self, height: f32) -> f32 {
+        if !self.limit_height_by_resolution {
+            return height;
+        }
+
+        let single_height = self.size.1 / self.resolution.1 as f32;
+        ((height + self.size.1 / 2.0) / single_height).round() * single_height - self.size.1 / 2.0
+    }
+
+    fn grid(&self) -> ColumnGrid {
+        ColumnGrid::new(
+            (self.resolution.0, self.resolution.2),
+            (self.size.0, self.size.2),
+        )
+    }
+
+    /// Applies the current instruction. Every segment of its move, or chord of its arc, is
+    /// swept in one step.
+    fn start_instruction(&mut self) {
+        let start = self.current_position;
+        let instruction = self.code.instructions()[self.current_instruction].clone();
+        let targets = self.machine_state.apply(&instruction, self.chord_tolerance);
+        if instruction.has_m(6) {
+            self.change_tool();
+        }
+
+        let mut points = targets
+            .into_iter()
+            .map(|target| self.to_scene(target))
+            .collect::<Vec<_>>();
+        if points.is_empty() {
+            points.push(start);
+        }
 
-                CutterPart::new((x, z), (x_offset, y_offset, z_offset))
+        let mut previous = start;
+        self.current_move_length = points
+            .iter()
+            .map(|&point| {
+                let length = distance(previous, point);
+                previous = point;
+                length
             })
-            .collect()
+            .sum();
+
+        let end = *points.last().unwrap();
+        self.current_move_is_plunge =
+            end.1 < start.1 && points.iter().all(|p| p.0 == start.0 && p.2 == start.2);
+        self.current_move_is_rapid = self.machine_state.last_move_rapid() && end != start;
+        self.current_points = Some(points);
+        self.current_point = Some(0);
     }
 
-    pub fn execution_finished(&self) -> bool {
-        self.current_instruction >= self.code.instructions().len() || self.error.is_some()
+    /// Machine time in seconds of the next step, its share of the time of the instruction by
+    /// length.
+    fn step_time(&self) -> f32 {
+        let (Some(current_points), Some(current_point)) =
+            (self.current_points.as_ref(), self.current_point)
+        else {
+            return 0.0;
+        };
+        let instruction_time = self.instruction_times[self.current_instruction];
+        if self.current_move_length <= f32::EPSILON {
+            return instruction_time;
+        }
+        let length = distance(self.current_position, current_points[current_point]);
+        instruction_time * length / self.current_move_length
+    }
+
+    /// Runs the program for `seconds` of machine time. Time not used up by a whole step is
+    /// carried over to the next call and moves the drawn tool part way along the step; the
+    /// material is cut once the step is reached.
+    pub fn advance(
+        &mut self,
+        height_map: &mut HeightData,
+        max_cutter_immersion: f32,
+        seconds: f32,
+    ) {
+        self.time_budget += seconds;
+        while !self.execution_finished() {
+            if self.current_points.is_none() {
+                self.start_instruction();
+            }
+            let step_time = self.step_time();
+            if step_time > self.time_budget {
+                let start = self.current_position;
+                let end = self.current_points.as_ref().unwrap()[self.current_point.unwrap()];
+                let t = self.time_budget / step_time;
+                self.tool_position = (
+                    start.0 + (end.0 - start.0) * t,
+                    start.1 + (end.1 - start.1) * t,
+                    start.2 + (end.2 - start.2) * t,
+                );
+                return;
+            }
+            self.time_budget -= step_time;
+            self.execute_step(height_map, max_cutter_immersion);
+        }
+        self.time_budget = 0.0;
     }
 
-    pub fn execute_step(&mut self, height_map: &mut HeightMap, max_cutter_immersion: f32) {
+    pub fn remaining_time(&self) -> f32 {
+        (self.program_time - self.elapsed_time).max(0.0)
+    }
+
+    fn update_program_time(&mut self) {
+        let estimate = TimeEstimate::new(
+            &self.code,
+            self.program_start_state.clone(),
+            self.chord_tolerance,
+            self.machine_setup.rapid_rate(),
+            &self.machine_setup.motion_limits(),
+        );
+        self.instruction_times = vec![0.0; self.code.instructions().len()];
+        for move_time in estimate.moves() {
+            self.instruction_times[move_time.instruction()] += move_time.time();
+        }
+        self.program_time = estimate.total_time();
+    }
+
+    pub fn execute_step(&mut self, height_map: &mut HeightData, max_cutter_immersion: f32) {
         if self.execution_finished() {
             return;
         }
 
-        let single_size = (
-            self.size.0 / (self.resolution.0 as f32),
-            self.size.1 / (self.resolution.1 as f32),
-            self.size.2 / (self.resolution.2 as f32),
-        );
+        let grid = self.grid();
         if self.current_points.is_none() {
-            let start = (
-                (self.current_position.0 / single_size.0) as i32,
-                (self.current_position.1 / single_size.1) as i32,
-                (self.current_position.2 / single_size.2) as i32,
-            );
-            let instruction = self.code.instructions()[self.current_instruction].clone();
-            let end_x = match instruction.normalized_x() {
-                Some(v) => (v / single_size.0 / 10.0) as i32,
-                None => start.0,
-            };
-            let end_y = match instruction.normalized_y() {
-                Some(v) => (v / single_size.1 / 10.0) as i32,
-                None => start.1,
-            };
-            let end_z = match instruction.normalized_z() {
-                Some(v) => (v / single_size.2 / 10.0) as i32,
-                None => start.2,
-            };
-            let end = (end_x, end_y, end_z);
-            self.current_points = Some(Bresenham3d::new(start, end).collect());
-            self.current_point = Some(0);
+            self.start_instruction();
         }
 
         let current_point = self.current_point.unwrap();
-        let current_points = self.current_points.as_mut().unwrap();
+        let step_time = self.step_time();
+        let current_points = self.current_points.as_ref().unwrap();
+        let start = self.current_position;
+        let end = current_points[current_point];
+        let last_point = current_points.len() - 1;
+        let non_cutting_radius = self.cutter_profile.non_cutting_radius() / 10.0;
+        let plunge = self.current_move_is_plunge && non_cutting_radius > 0.0;
+        let rapid = self.current_move_is_rapid;
+        let remove_on_rapid = self.remove_material_on_rapid;
+        let mut deepest_contact: Option<((usize, usize), f32, f32)> = None;
+        let bottom = -self.size.1 / 2.0;
 
-        let point = current_points[current_point];
+        let mut found = vec![];
+        let mut stopped = false;
+        let policy = &self.error_policy;
+        let mut found_kind = |kind: ExecutionErrorKind, position: (f32, f32, f32)| {
+            if !found.iter().any(|&(k, _)| k == kind) {
+                found.push((kind, position));
+            }
+            policy.stops(kind)
+        };
+        let cutter_profile = self.cutter_profile.as_ref();
+        let (start_tip, tip) = (self.to_machine(start), self.to_machine(end));
+        let lowest_tip = if start_tip.2 < tip.2 { start } else { end };
+        if self
+            .machine_setup
+            .base_plate_height()
+            .is_some_and(|base_plate_height| start_tip.2.min(tip.2) < base_plate_height)
+            && found_kind(ExecutionErrorKind::BelowSafetyFloor, lowest_tip)
+        {
+            stopped = true;
+        }
+        if self
+            .machine_setup
+            .fixtures()
+            .iter()
+            .any(|fixture| fixture.collides(start_tip, tip, cutter_profile))
+            && found_kind(ExecutionErrorKind::FixtureCollision, end)
+        {
+            stopped = true;
+        }
 
-        self.current_position = (
-            single_size.0 * point.0 as f32,
-            single_size.1 * point.1 as f32,
-            single_size.2 * point.2 as f32,
-        );
+        let max_feed = self
+            .active_tool
+            .and_then(|number| self.code.tools().get(&number))
+            .and_then(|tool| tool.max_feed());
+        if !rapid
+            && start != end
+            && max_feed
+                .zip(self.machine_state.feed())
+                .is_some_and(|(max_feed, feed)| feed > max_feed)
+            && found_kind(ExecutionErrorKind::FeedAboveToolLimit, end)
+        {
+            stopped = true;
+        }
+        if current_point == 0
+            && self.machine_state.last_arc_invalid()
+            && found_kind(ExecutionErrorKind::ArcRadiusMismatch, start)
+        {
+            stopped = true;
+        }
 
-        for c in self.cutter.iter() {
-            let xs = [
-                point.0 + self.resolution.0 as i32 / 2 + c.index_offset.0 as i32,
-                point.0 + self.resolution.0 as i32 / 2 - c.index_offset.0 as i32,
-            ];
-            let zs = [
-                point.2 + self.resolution.2 as i32 / 2 + c.index_offset.1 as i32,
-                point.2 + self.resolution.2 as i32 / 2 - c.index_offset.1 as i32,
-            ];
-            for (x, z) in xs.iter().flat_map(|x| zs.iter().map(|z| (*x, *z))) {
-                if x >= 0 && x < self.resolution.0 as i32 && z >= 0 && z < self.resolution.2 as i32
-                {
-                    let index = (x as usize, z as usize);
+        // Material left above the flutes hits the shank, and above the shank the holder.
+        let non_cutting_parts = [
+            cutter_profile.cutting_length().map(|cutting_length| {
+                (
+                    Cylinder::new(cutter_profile.shank_radius(), cutting_length),
+                    ExecutionErrorKind::ShankCollision,
+                )
+            }),
+            cutter_profile
+                .overall_length()
+                .zip(cutter_profile.holder())
+                .map(|(overall_length, holder)| {
+                    (
+                        Cylinder::new(holder.diameter() / 2.0, overall_length),
+                        ExecutionErrorKind::HolderCollision,
+                    )
+                }),
+        ];
+        for (part, kind) in non_cutting_parts.into_iter().flatten() {
+            let mut contact = None;
+            sweep(start, end, &part, &grid, |index, height, _| {
+                if contact.is_none() && height_map.get_height(index) > height {
+                    let column = grid.column_position(index);
+                    contact = Some((column.0, height, column.1));
+                }
+            });
+            if let Some(contact) = contact {
+                if found_kind(kind, contact) {
+                    stopped = true;
+                }
+            }
+        }
+
+        sweep(
+            start,
+            end,
+            cutter_profile,
+            &grid,
+            |index, height, distance| {
+                if stopped {
+                    return;
+                }
 
-                    let immersion = height_map.get_height(index) - self.current_position.1;
+                let current_height = height_map.get_height(index);
+                let column = grid.column_position(index);
+                let contact = (column.0, height, column.1);
+                let tip_height = height - cutter_profile.height_at(distance * 10.0) / 10.0;
+                if current_height - tip_height > max_cutter_immersion
+                    && found_kind(ExecutionErrorKind::ExceededImmersion, contact)
+                {
+                    stopped = true;
+                    return;
+                }
 
-                    if immersion > max_cutter_immersion {
-                        self.error = Some(ExecutionError::new_too_deep_immersion());
+                let mut height = self.snap_height(height);
+                if height < bottom {
+                    if found_kind(ExecutionErrorKind::BelowStockBottom, contact) {
+                        stopped = true;
                         return;
                     }
+                    height = bottom;
+                }
 
-                    if height_map.get_height(index) > self.current_position.1 + c.position_offset.1
-                    {
-                        let is_vertical = {
-                            match self.code.cutter() {
-                                MillingCutter::Flat(_) => {
-                                    let first = current_points.first().unwrap();
-                                    let last = current_points.last().unwrap();
-
-                                    first.0 == last.0 && first.2 == last.2 && first.1 != last.1
-                                }
-                                MillingCutter::Spherical(_) => false,
-                            }
-                        };
-
-                        if is_vertical {
-                            self.error = Some(ExecutionError::new_vertical_cut());
+                if current_height > height {
+                    if rapid {
+                        let depth = current_height - height;
+                        if deepest_contact.is_none_or(|(_, _, deepest)| depth > deepest) {
+                            deepest_contact = Some((index, current_height, depth));
+                        }
+                        if found_kind(ExecutionErrorKind::RapidIntoMaterial, contact) {
+                            stopped = true;
+                            return;
+                        }
+                        if !remove_on_rapid {
                             return;
                         }
+                    }
 
-                        height_map.write(index, self.current_position.1 + c.position_offset.1);
+                    if plunge
+                        && distance < non_cutting_radius
+                        && found_kind(ExecutionErrorKind::PlungeWithFlatCutter, contact)
+                    {
+                        stopped = true;
+                        return;
+                    }
+
+                    height_map.write(index, height);
+                    if rapid {
+                        height_map.highlight(index);
                     }
                 }
-            }
+            },
+        );
+
+        let reach = self.cutter_profile.radius() / 10.0;
+        if end.0.abs() > self.size.0 / 2.0 + reach || end.2.abs() > self.size.2 / 2.0 + reach {
+            found_kind(ExecutionErrorKind::OutOfBounds, end);
         }
 
-        if current_point >= current_points.len() - 1 {
+        if let Some((index, surface, depth)) = deepest_contact {
+            let column = grid.column_position(index);
+            self.record_rapid_collision(
+                self.to_machine((column.0, surface, column.1)),
+                depth * 10.0,
+            );
+        }
+
+        // A stopped step leaves the tool where it started, as its material was not removed.
+        if !stopped {
+            self.current_position = end;
+            self.tool_position = end;
+            self.elapsed_time += step_time;
+        }
+        for (kind, position) in found {
+            self.report(kind, position);
+        }
+        if self.error.is_some() {
+            return;
+        }
+
+        if current_point >= last_point {
             self.current_point = None;
             self.current_points = None;
             self.current_instruction += 1;
@@ -249,3 +733,497 @@ impl GCodeExecutor {
         }
     }
 }
+
+fn distance(from: (f32, f32, f32), to: (f32, f32, f32)) -> f32 {
+    ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2) + (to.2 - from.2).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{ErrorPolicy, ExecutionErrorKind, GCodeExecutor};
+    use crate::{
+        cutter_profile::ToolHolder,
+        fixture::Fixture,
+        g_code::GCode,
+        height_map::HeightData,
+        milling_cutter::{CutterShape, MillingCutter},
+        time_estimate::{MotionLimits, TimeEstimate},
+        tool_library::ToolLibrary,
+    };
+
+    const RESOLUTION: (u32, u32, u32) = (100, 50, 100);
+    const SIZE: (f32, f32, f32) = (10.0, 5.0, 10.0);
+
+    fn executor(program: &str, cutter: MillingCutter) -> GCodeExecutor {
+        let code = GCode::parse(program, None, Some(cutter), false, None).unwrap();
+        GCodeExecutor::new(code, RESOLUTION, SIZE, false)
+    }
+
+    /// Index of the column below the machine position `(x, y)`.
+    fn column(executor: &GCodeExecutor, position: (f32, f32)) -> (usize, usize) {
+        let scene = executor.to_scene((position.0, position.1, 0.0));
+        (
+            ((scene.0 / 0.1).round() as i32 + 50) as usize,
+            ((scene.2 / 0.1).round() as i32 + 50) as usize,
+        )
+    }
+
+    fn run(executor: &mut GCodeExecutor, height_data: &mut HeightData, max_cutter_immersion: f32) {
+        while !executor.execution_finished() {
+            executor.execute_step(height_data, max_cutter_immersion);
+        }
+    }
+
+    /// Makes moves take their length over their rate.
+    fn without_acceleration(executor: &mut GCodeExecutor) {
+        let mut machine_setup = executor.machine_setup().clone();
+        machine_setup.set_motion_limits(
+            MotionLimits::new((1e9, 1e9, 1e9), (1e12, 1e12, 1e12), 0.02).unwrap(),
+        );
+        executor.set_machine_setup(machine_setup);
+    }
+
+    fn flat_cutter() -> MillingCutter {
+        MillingCutter::new(CutterShape::Flat, 10.0, None, None)
+    }
+
+    fn ball_cutter() -> MillingCutter {
+        MillingCutter::new(CutterShape::Spherical, 10.0, None, None)
+    }
+
+    #[rstest]
+    #[case::exceeded_immersion(
+        "N1G00X-80Y0Z10\nN2G01X0F500\n",
+        ball_cutter(),
+        ExecutionErrorKind::ExceededImmersion,
+        1.0,
+        (-50.0, 0.0, 15.0)
+    )]
+    #[case::below_stock_bottom(
+        "N1G00X0Y0Z30\nN2G01Z-30F100\n",
+        flat_cutter(),
+        ExecutionErrorKind::BelowStockBottom,
+        100.0,
+        (0.0, 0.0, -30.0)
+    )]
+    #[case::plunge_with_flat_cutter(
+        "N1G00X0Y0Z30\nN2G01Z20F100\n",
+        flat_cutter(),
+        ExecutionErrorKind::PlungeWithFlatCutter,
+        1.0,
+        (0.0, 0.0, 20.0)
+    )]
+    fn problem_is_reported_at_contact(
+        #[case] program: &str,
+        #[case] cutter: MillingCutter,
+        #[case] kind: ExecutionErrorKind,
+        #[case] max_cutter_immersion: f32,
+        #[case] position: (f32, f32, f32),
+    ) {
+        let mut executor = executor(program, cutter);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, max_cutter_immersion);
+
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), kind);
+        assert_eq!(error.instruction(), 1);
+        assert_eq!(error.block(), "N2");
+        // The contact is a column under the cutter, not the tool tip at the end of the step.
+        let found = error.position();
+        assert!(
+            ((found.0 - position.0).powi(2) + (found.1 - position.1).powi(2)).sqrt() <= 5.0
+                && (found.2 - position.2).abs() < 1.0,
+            "{:?} != {:?}",
+            found,
+            position
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_is_reported_once_per_instruction() {
+        let mut executor = executor("N1G00X100Y0Z30\nN2G02X120Y0I10J0\n", flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        assert!(executor.error().is_none());
+        let warnings = executor
+            .warnings()
+            .iter()
+            .map(|warning| (warning.instruction(), warning.kind()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (0, ExecutionErrorKind::OutOfBounds),
+                (1, ExecutionErrorKind::OutOfBounds)
+            ]
+        );
+    }
+
+    #[test]
+    fn stopping_kind_ends_the_program() {
+        let mut executor = executor("N1G00X100Y0Z30\nN2G00X0\n", flat_cutter());
+        let mut policy = ErrorPolicy::default();
+        policy.set_stops(ExecutionErrorKind::OutOfBounds, true);
+        executor.set_error_policy(policy);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        assert!(executor.warnings().is_empty());
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), ExecutionErrorKind::OutOfBounds);
+        assert_eq!(error.instruction(), 0);
+        assert_eq!(*executor.current_instruction(), 0);
+    }
+
+    #[test]
+    fn cut_lowers_the_stock() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G01X0F500\n", flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        assert!(executor.error().is_none());
+        let slot = column(&executor, (-25.0, 0.0));
+        let beside = column(&executor, (-25.0, 6.0));
+        assert!((height_data.get_height(slot) - 2.0).abs() < 1e-5);
+        assert!((height_data.get_height(beside) - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn straight_move_is_swept_in_one_step() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G01X0F500\n", flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        executor.execute_step(&mut height_data, 1.0);
+        executor.execute_step(&mut height_data, 1.0);
+
+        assert!(executor.execution_finished());
+        let slot = column(&executor, (-25.0, 0.0));
+        assert!((height_data.get_height(slot) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn partial_step_only_moves_drawn_tool() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G01X0F500\n", flat_cutter());
+        without_acceleration(&mut executor);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        let rapid_time = (80.0f32.powi(2) + 200.0f32.powi(2)).sqrt() / 5000.0 * 60.0;
+
+        executor.advance(&mut height_data, 1.0, rapid_time + 4.8);
+
+        let drawn = executor.to_machine(executor.tool_position());
+        assert!((drawn.0 + 40.0).abs() < 1e-2, "{:?}", drawn);
+        assert_eq!(*executor.current_instruction(), 1);
+        let slot = column(&executor, (-40.0, 0.0));
+        assert!((height_data.get_height(slot) - 2.5).abs() < 1e-5);
+
+        executor.advance(&mut height_data, 1.0, 4.9);
+
+        assert!(executor.execution_finished());
+        assert!((height_data.get_height(slot) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn moves_take_feed_or_rapid_rate() {
+        let mut executor = executor("N1G00X100Y0Z220\nN2G01X0F600\n", flat_cutter());
+        without_acceleration(&mut executor);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        executor.execute_step(&mut height_data, 1.0);
+        assert!((executor.elapsed_time() - 1.2).abs() < 1e-3);
+
+        executor.execute_step(&mut height_data, 1.0);
+        assert!((executor.elapsed_time() - 11.2).abs() < 1e-3);
+        assert!((executor.program_time() - 11.2).abs() < 1e-3);
+        assert!(executor.remaining_time() < 1e-3);
+    }
+
+    #[test]
+    fn program_time_follows_time_estimate() {
+        let program = "N1G00X-30Y0Z40\nN2G01Z30F300\nN3G02X30I30J0F800\nN4G00Z100\n";
+        let mut executor = executor(program, flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        let estimate = TimeEstimate::new(
+            executor.code(),
+            executor.machine_setup().initial_state(),
+            executor.chord_tolerance(),
+            executor.machine_setup().rapid_rate(),
+            &executor.machine_setup().motion_limits(),
+        );
+
+        assert!((executor.program_time() - estimate.total_time()).abs() < 1e-4);
+
+        executor.advance(&mut height_data, 10.0, estimate.total_time() + 1.0);
+
+        assert!(executor.execution_finished());
+        assert!((executor.elapsed_time() - estimate.total_time()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn unused_time_is_carried_over() {
+        // The feed move takes 20 s of the 21.2 s, longer than any single call below.
+        let mut executor = executor("N1G00X100Y0Z220\nN2G01X0F300\n", flat_cutter());
+        without_acceleration(&mut executor);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        executor.advance(&mut height_data, 1.0, 2.0);
+        assert_eq!(*executor.current_instruction(), 1);
+        assert!((executor.elapsed_time() - 1.2).abs() < 1e-3);
+
+        for _ in 0..19 {
+            executor.advance(&mut height_data, 1.0, 1.0);
+        }
+        assert!(!executor.execution_finished());
+        let drawn = executor.to_machine(executor.tool_position());
+        assert!((drawn.0 - 1.0).abs() < 1e-2, "{:?}", drawn);
+
+        executor.advance(&mut height_data, 1.0, 0.25);
+        assert!(executor.execution_finished());
+        assert!((executor.elapsed_time() - 21.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rapid_collision_keeps_deepest_contact() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G00X0\n", flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        let bump = column(&executor, (-30.0, 0.0));
+        height_data.write(bump, 3.5);
+
+        run(&mut executor, &mut height_data, 10.0);
+
+        assert!(executor.error().is_none());
+        let collisions = executor.rapid_collisions();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].instruction(), 1);
+        assert_eq!(collisions[0].block(), "N2");
+        assert!((collisions[0].depth() - 15.0).abs() < 1e-3);
+        let contact = collisions[0].contact();
+        assert!((contact.0 + 30.0).abs() < 1e-3, "{:?}", contact);
+        assert!(contact.1.abs() < 1e-3, "{:?}", contact);
+        assert!((contact.2 - 35.0).abs() < 1e-3, "{:?}", contact);
+        assert_eq!(height_data.get_height(bump), 3.5);
+        assert!(!height_data.is_highlighted(bump));
+    }
+
+    #[test]
+    fn rapid_removes_and_highlights_material_when_enabled() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G00X0\n", flat_cutter());
+        executor.set_remove_material_on_rapid(true);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 10.0);
+
+        assert_eq!(executor.rapid_collisions().len(), 1);
+        let slot = column(&executor, (-25.0, 0.0));
+        let beside = column(&executor, (-25.0, 6.0));
+        assert!((height_data.get_height(slot) - 2.0).abs() < 1e-5);
+        assert!(height_data.is_highlighted(slot));
+        assert!((height_data.get_height(beside) - 2.5).abs() < 1e-5);
+        assert!(!height_data.is_highlighted(beside));
+    }
+
+    /// Stock with a 40 mm square pocket around the machine zero, its floor at `floor` mm.
+    fn pocket(executor: &GCodeExecutor, floor: f32) -> HeightData {
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        for x in 0..RESOLUTION.0 as usize {
+            for z in 0..RESOLUTION.2 as usize {
+                let scene = ((x as f32 - 50.0) * 0.1, 0.0, (z as f32 - 50.0) * 0.1);
+                let machine = executor.to_machine(scene);
+                if machine.0.abs() <= 20.0 && machine.1.abs() <= 20.0 {
+                    height_data.write((x, z), floor / 10.0);
+                }
+            }
+        }
+        height_data
+    }
+
+    fn long_shank_cutter() -> MillingCutter {
+        MillingCutter::new(CutterShape::Flat, 10.0, Some(10.0), None).with_shank_diameter(16.0)
+    }
+
+    fn held_cutter() -> MillingCutter {
+        MillingCutter::new(CutterShape::Flat, 10.0, None, Some(30.0))
+            .with_holder(ToolHolder::new(50.0, 40.0))
+    }
+
+    #[rstest]
+    #[case::shank_in_deep_pocket(
+        long_shank_cutter(),
+        10.0,
+        Some(ExecutionErrorKind::ShankCollision)
+    )]
+    #[case::shank_in_shallow_pocket(long_shank_cutter(), 20.0, None)]
+    #[case::holder_in_deep_pocket(held_cutter(), -10.0, Some(ExecutionErrorKind::HolderCollision))]
+    #[case::holder_in_shallow_pocket(held_cutter(), 20.0, None)]
+    fn non_cutting_parts_hit_pocket_walls(
+        #[case] cutter: MillingCutter,
+        #[case] floor: f32,
+        #[case] kind: Option<ExecutionErrorKind>,
+    ) {
+        let program = format!("N1G00X0Y0Z50\nN2G01Z{}F500\nN3G01X14\n", floor);
+        let mut executor = executor(&program, cutter);
+        let mut height_data = pocket(&executor, floor);
+
+        run(&mut executor, &mut height_data, 100.0);
+
+        assert_eq!(executor.error().as_ref().map(|error| error.kind()), kind);
+        assert!(executor.warnings().is_empty());
+    }
+
+    #[rstest]
+    #[case(None, None)]
+    #[case(Some(-50.0), None)]
+    #[case(Some(-40.0), Some(ExecutionErrorKind::BelowSafetyFloor))]
+    fn base_plate_is_checked_beside_the_stock(
+        #[case] base_plate_height: Option<f32>,
+        #[case] kind: Option<ExecutionErrorKind>,
+    ) {
+        let mut executor = executor("N1G00X-80Y0Z-30\nN2G01Z-45F500\n", flat_cutter());
+        let mut machine_setup = executor.machine_setup().clone();
+        machine_setup.set_base_plate_height(base_plate_height);
+        executor.set_machine_setup(machine_setup);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        assert_eq!(executor.error().as_ref().map(|error| error.kind()), kind);
+        if let Some(error) = executor.error() {
+            assert_eq!(error.instruction(), 1);
+            assert_eq!(error.position(), (-80.0, 0.0, -45.0));
+        }
+    }
+
+    #[test]
+    fn fixture_between_step_ends_collides() {
+        let mut executor = executor("N1G00X-60Y0Z35\nN2G01X0F500\n", flat_cutter());
+        let mut machine_setup = executor.machine_setup().clone();
+        machine_setup.set_fixtures(vec![Fixture::new(
+            "clamp".to_string(),
+            (-20.0, -10.0, 30.0),
+            (-10.0, 10.0, 40.0),
+        )]);
+        executor.set_machine_setup(machine_setup);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), ExecutionErrorKind::FixtureCollision);
+        assert_eq!(error.instruction(), 1);
+    }
+
+    #[test]
+    fn stopped_step_leaves_tool_at_start() {
+        let mut executor = executor("N1G00X-80Y0Z20\nN2G01X0F500\n", flat_cutter());
+        let mut machine_setup = executor.machine_setup().clone();
+        machine_setup.set_fixtures(vec![Fixture::new(
+            "clamp".to_string(),
+            (-30.0, -10.0, 15.0),
+            (-20.0, 10.0, 25.0),
+        )]);
+        executor.set_machine_setup(machine_setup);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), ExecutionErrorKind::FixtureCollision);
+        let start = executor.to_scene((-80.0, 0.0, 20.0));
+        assert_eq!(executor.tool_position(), start);
+        assert_eq!(*executor.current_position(), start);
+        let slot = column(&executor, (-50.0, 0.0));
+        assert!((height_data.get_height(slot) - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn first_stopping_problem_is_kept() {
+        let mut executor = executor("N1G00X-80Y0Z-30\nN2G01Z-45F500\n", flat_cutter());
+        let mut machine_setup = executor.machine_setup().clone();
+        machine_setup.set_base_plate_height(Some(-40.0));
+        machine_setup.set_fixtures(vec![Fixture::new(
+            "vise".to_string(),
+            (-90.0, -10.0, -50.0),
+            (-70.0, 10.0, -42.0),
+        )]);
+        executor.set_machine_setup(machine_setup);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), ExecutionErrorKind::BelowSafetyFloor);
+        assert!(executor
+            .warnings()
+            .iter()
+            .any(|warning| warning.instruction() == 1
+                && warning.kind() == ExecutionErrorKind::FixtureCollision));
+    }
+
+    #[rstest]
+    #[case("N2G02X-60Y0R5F500\n")]
+    #[case("N2G02X-60Y0I5J0F500\n")]
+    fn arc_with_mismatched_radius_stops(#[case] arc: &str) {
+        let program = format!("N1G00X-80Y0Z20\n{}", arc);
+        let mut executor = executor(&program, flat_cutter());
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        let error = executor.error().as_ref().unwrap();
+        assert_eq!(error.kind(), ExecutionErrorKind::ArcRadiusMismatch);
+        assert_eq!(error.instruction(), 1);
+        assert_eq!(error.position(), (-80.0, 0.0, 20.0));
+    }
+
+    #[test]
+    fn non_positive_chord_tolerance_is_rejected() {
+        let mut executor = executor("N1G00X0Y0Z50\n", flat_cutter());
+
+        assert!(executor.set_chord_tolerance(0.0).is_err());
+        assert!(executor.set_chord_tolerance(-1.0).is_err());
+        assert_eq!(executor.chord_tolerance(), 0.01);
+    }
+
+    #[test]
+    fn feed_above_tool_limit_is_reported() {
+        let library = ToolLibrary::from_json(
+            r#"{ "tools": [{ "number": 1, "shape": "Flat", "diameter": 10.0, "max_feed": 1000.0 }] }"#,
+        )
+        .unwrap();
+        let program = "N1T1M06\nN2G00X-80Y0Z20\nN3G01X-70F2000\nN4G01X-60F500\n";
+        let code = GCode::parse(program, None, None, false, Some(&library)).unwrap();
+        let mut executor = GCodeExecutor::new(code, RESOLUTION, SIZE, false);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+
+        run(&mut executor, &mut height_data, 1.0);
+
+        assert!(executor.error().is_none());
+        let warnings = executor
+            .warnings()
+            .iter()
+            .filter(|warning| warning.kind() == ExecutionErrorKind::FeedAboveToolLimit)
+            .map(|warning| warning.instruction())
+            .collect::<Vec<_>>();
+        assert_eq!(warnings, vec![2]);
+    }
+
+    #[test]
+    fn error_policy_can_be_changed() {
+        let mut policy = ErrorPolicy::default();
+        assert!(policy.stops(ExecutionErrorKind::ExceededImmersion));
+        assert!(!policy.stops(ExecutionErrorKind::RapidIntoMaterial));
+
+        policy.set_stops(ExecutionErrorKind::ExceededImmersion, false);
+        policy.set_stops(ExecutionErrorKind::RapidIntoMaterial, true);
+        policy.set_stops(ExecutionErrorKind::RapidIntoMaterial, true);
+
+        assert!(!policy.stops(ExecutionErrorKind::ExceededImmersion));
+        assert!(policy.stops(ExecutionErrorKind::RapidIntoMaterial));
+    }
+}
@@ -2,7 +2,12 @@ use glium::glutin::surface::WindowSurface;
 use glium::{uniform, Display, DrawParameters, Frame, Program, Surface, VertexBuffer};
 use nalgebra::{Matrix4, Vector3};
 
-use crate::vertex::SmallVertex;
+use crate::{cutter_profile::CutterProfile, vertex::SmallVertex};
+
+const DEFAULT_TOOL_LENGTH: f32 = 20.0;
+const FLUTES_COLOR: [f32; 3] = [0.8, 0.0, 0.0];
+const SHANK_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+const HOLDER_COLOR: [f32; 3] = [0.3, 0.3, 0.35];
 
 pub struct GCodeExecutorDrawer {
     program: Program,
@@ -30,7 +35,7 @@ impl GCodeExecutorDrawer {
 
             out vec4 frag_color;
 
-            const vec3 color = vec3(0.8, 0.0, 0.0);
+            uniform vec3 color;
 
             void main() {
                 frag_color = vec4(color, 1.0);
@@ -40,8 +45,8 @@ impl GCodeExecutorDrawer {
         let program =
             Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap();
 
-        let size = 0.4;
-        let y_size = 2.0;
+        let size = 1.0;
+        let y_size = 1.0;
 
         let vertex_buffer = VertexBuffer::new(
             display,
@@ -98,11 +103,66 @@ impl GCodeExecutorDrawer {
         perspective: &Matrix4<f32>,
         view_matrix: &Matrix4<f32>,
         position: (f32, f32, f32),
+        cutter_profile: &dyn CutterProfile,
+        drawing_parameters: &DrawParameters,
+    ) {
+        let overall_length = cutter_profile
+            .overall_length()
+            .or(cutter_profile.cutting_length())
+            .unwrap_or(DEFAULT_TOOL_LENGTH);
+        let cutting_length = cutter_profile
+            .cutting_length()
+            .unwrap_or(overall_length)
+            .min(overall_length);
+
+        let mut parts = vec![
+            (cutter_profile.radius(), 0.0, cutting_length, FLUTES_COLOR),
+            (
+                cutter_profile.shank_radius(),
+                cutting_length,
+                overall_length,
+                SHANK_COLOR,
+            ),
+        ];
+        if let Some(holder) = cutter_profile.holder() {
+            parts.push((
+                holder.diameter() / 2.0,
+                overall_length,
+                overall_length + holder.length(),
+                HOLDER_COLOR,
+            ));
+        }
+
+        for (radius, bottom, top, color) in parts {
+            if top > bottom {
+                self.draw_part(
+                    target,
+                    perspective,
+                    view_matrix,
+                    (position.0, position.1 + bottom / 10.0, position.2),
+                    (radius / 10.0, (top - bottom) / 10.0),
+                    color,
+                    drawing_parameters,
+                );
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_part(
+        &self,
+        target: &mut Frame,
+        perspective: &Matrix4<f32>,
+        view_matrix: &Matrix4<f32>,
+        position: (f32, f32, f32),
+        (radius, length): (f32, f32),
+        color: [f32; 3],
         drawing_parameters: &DrawParameters,
     ) {
         let index_buffer = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
 
-        let model = Matrix4::new_translation(&Vector3::new(position.0, position.1, position.2));
+        let model = Matrix4::new_translation(&Vector3::new(position.0, position.1, position.2))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(radius, length, radius));
 
         target
             .draw(
@@ -113,8 +173,9 @@ impl GCodeExecutorDrawer {
                     perspective: perspective.data.0,
                     view: view_matrix.data.0,
                     model: model.data.0,
+                    color: color,
                 },
-                &drawing_parameters,
+                drawing_parameters,
             )
             .unwrap();
     }
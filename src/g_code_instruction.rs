@@ -1,79 +1,330 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
 use derive_getters::Getters;
+use derive_new::new;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionMode {
+    Rapid,
+    Linear,
+    ClockwiseArc,
+    CounterClockwiseArc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plane {
+    XY,
+    ZX,
+    YZ,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Positioning {
+    Absolute,
+    Relative,
+}
 
-#[derive(Debug, Clone, Getters)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    Millimeters,
+    Inches,
+}
+
+#[derive(Debug, Clone, Default, Getters)]
 pub struct GCodeInstruction {
+    /// N word of the block, if it has one.
+    #[getter(copy)]
+    n: Option<u32>,
+    /// Line of the block in the program, counting from 1, or 0 if it was parsed on its own.
     #[getter(copy)]
-    n: u32,
+    line: usize,
+    g: Vec<u32>,
+    m: Vec<u32>,
     #[getter(copy)]
     x: Option<f32>,
     #[getter(copy)]
     y: Option<f32>,
     #[getter(copy)]
     z: Option<f32>,
+    #[getter(copy)]
+    i: Option<f32>,
+    #[getter(copy)]
+    j: Option<f32>,
+    #[getter(copy)]
+    k: Option<f32>,
+    #[getter(copy)]
+    r: Option<f32>,
+    #[getter(copy)]
+    f: Option<f32>,
+    #[getter(copy)]
+    s: Option<f32>,
+    #[getter(copy)]
+    t: Option<u32>,
+    comments: Vec<String>,
+    /// Words the simulator does not act on, like the dwell time of G04 or the H offset of G43.
+    ignored_words: Vec<ParseError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedCharacter(char),
+    InvalidValue(char, String),
+    IgnoredWord(char),
+    DuplicateWord(char),
+    MissingCommand,
+    UnterminatedComment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+pub struct ParseError {
+    #[getter(copy)]
+    column: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            ParseErrorKind::InvalidValue(letter, value) => {
+                write!(f, "invalid value '{}' for word {}", value, letter)
+            }
+            ParseErrorKind::IgnoredWord(letter) => write!(f, "word {} is ignored", letter),
+            ParseErrorKind::DuplicateWord(letter) => write!(f, "word {} given twice", letter),
+            ParseErrorKind::MissingCommand => write!(f, "line has no words besides N"),
+            ParseErrorKind::UnterminatedComment => write!(f, "comment is not closed"),
+        }
+    }
+}
+
+struct Word<'a> {
+    column: usize,
+    letter: char,
+    value: &'a str,
+}
+
+impl Word<'_> {
+    fn parse<T: FromStr>(&self) -> Result<T, ParseError> {
+        self.value.parse::<T>().map_err(|_| {
+            ParseError::new(
+                self.column,
+                ParseErrorKind::InvalidValue(self.letter, self.value.to_string()),
+            )
+        })
+    }
+
+    fn set_once<T: FromStr>(&self, word: &mut Option<T>) -> Result<(), ParseError> {
+        if word.is_some() {
+            return Err(ParseError::new(
+                self.column,
+                ParseErrorKind::DuplicateWord(self.letter),
+            ));
+        }
+        *word = Some(self.parse()?);
+        Ok(())
+    }
+}
+
+pub fn split_comments(line: &str) -> Result<(String, Vec<String>), ParseError> {
+    let mut code = String::with_capacity(line.len());
+    let mut comments = Vec::new();
+    let mut comment_start = None;
+
+    for (column, c) in line.chars().enumerate() {
+        match (comment_start, c) {
+            (None, ';') => {
+                comments.push(line.chars().skip(column + 1).collect::<String>());
+                break;
+            }
+            (None, '(') => {
+                comment_start = Some(column);
+                code.push(' ');
+            }
+            (None, c) => code.push(c),
+            (Some(start), ')') => {
+                comments.push(
+                    line.chars()
+                        .skip(start + 1)
+                        .take(column - start - 1)
+                        .collect(),
+                );
+                comment_start = None;
+                code.push(' ');
+            }
+            (Some(_), _) => code.push(' '),
+        }
+    }
+
+    if let Some(start) = comment_start {
+        return Err(ParseError::new(
+            start + 1,
+            ParseErrorKind::UnterminatedComment,
+        ));
+    }
+
+    let comments = comments
+        .into_iter()
+        .map(|comment| comment.trim().to_string())
+        .filter(|comment| !comment.is_empty())
+        .collect();
+
+    Ok((code, comments))
 }
 
 impl GCodeInstruction {
-    pub fn parse(line: &str) -> Option<GCodeInstruction> {
-        let instruction = line.trim();
-        let n_begin = instruction.find("N")?;
-        let g_begin = instruction.find("G")?;
-        let n = instruction[(n_begin + 1)..g_begin].parse::<u32>().ok()?;
-
-        let x_begin = instruction.find("X");
-        let y_begin = instruction.find("Y");
-        let z_begin = instruction.find("Z");
-
-        let mut x = None;
-        let mut y = None;
-        let mut z = None;
-
-        if let Some(x_begin) = x_begin {
-            let x_end = if y_begin.is_some() {
-                y_begin
-            } else if z_begin.is_some() {
-                z_begin
-            } else {
-                None
-            };
-
-            let x_as_str = match x_end {
-                Some(x_end) => &instruction[(x_begin + 1)..x_end],
-                None => &instruction[(x_begin + 1)..],
-            };
-
-            x = Some(x_as_str.parse::<f32>().ok()?);
+    /// Parses one block. Blocks without G, M or T words continue the modal motion, and words
+    /// the simulator does not use are kept in `ignored_words`.
+    pub fn parse(line: &str) -> Result<GCodeInstruction, ParseError> {
+        let mut instruction = GCodeInstruction::default();
+        let words = Self::split_words(line)?;
+        let has_command = words.iter().any(|word| word.letter != 'N');
+
+        for word in words {
+            match word.letter {
+                'N' => word.set_once(&mut instruction.n)?,
+                'G' => instruction.g.push(word.parse()?),
+                'M' => instruction.m.push(word.parse()?),
+                'X' => word.set_once(&mut instruction.x)?,
+                'Y' => word.set_once(&mut instruction.y)?,
+                'Z' => word.set_once(&mut instruction.z)?,
+                'I' => word.set_once(&mut instruction.i)?,
+                'J' => word.set_once(&mut instruction.j)?,
+                'K' => word.set_once(&mut instruction.k)?,
+                'R' => word.set_once(&mut instruction.r)?,
+                'F' => word.set_once(&mut instruction.f)?,
+                'S' => word.set_once(&mut instruction.s)?,
+                'T' => word.set_once(&mut instruction.t)?,
+                letter => {
+                    word.parse::<f32>()?;
+                    instruction.ignored_words.push(ParseError::new(
+                        word.column,
+                        ParseErrorKind::IgnoredWord(letter),
+                    ));
+                }
+            }
+        }
+
+        if !has_command {
+            return Err(ParseError::new(
+                line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+                ParseErrorKind::MissingCommand,
+            ));
         }
 
-        if let Some(y_begin) = y_begin {
-            let y_end = z_begin;
+        Ok(instruction)
+    }
+
+    fn split_words(line: &str) -> Result<Vec<Word<'_>>, ParseError> {
+        let mut words = Vec::new();
+        let mut chars = line.char_indices().enumerate().peekable();
 
-            let y_as_str = match y_end {
-                Some(y_end) => &instruction[(y_begin + 1)..y_end],
-                None => &instruction[(y_begin + 1)..],
-            };
+        while let Some((column, (_, letter))) = chars.next() {
+            if letter.is_whitespace() {
+                continue;
+            }
+            if !letter.is_ascii_alphabetic() {
+                return Err(ParseError::new(
+                    column + 1,
+                    ParseErrorKind::UnexpectedCharacter(letter),
+                ));
+            }
 
-            y = Some(y_as_str.parse::<f32>().ok()?);
+            let begin = chars.peek().map_or(line.len(), |&(_, (i, _))| i);
+            let mut end = begin;
+            while let Some(&(_, (i, c))) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            words.push(Word {
+                column: column + 1,
+                letter: letter.to_ascii_uppercase(),
+                value: &line[begin..end],
+            });
         }
 
-        if let Some(z_begin) = z_begin {
-            let z_as_str = &instruction[(z_begin + 1)..];
+        Ok(words)
+    }
 
-            z = Some(z_as_str.parse::<f32>().ok()?);
+    pub fn with_comments(mut self, comments: Vec<String>) -> Self {
+        self.comments.extend(comments);
+        self
+    }
+
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+
+    /// Names the block in messages by its N word, or by its line when it has none.
+    pub fn label(&self) -> String {
+        match self.n {
+            Some(n) => format!("N{}", n),
+            None => format!("line {}", self.line),
         }
+    }
 
-        Some(GCodeInstruction { n, x, y, z })
+    pub fn motion_mode(&self) -> Option<MotionMode> {
+        self.g.iter().rev().find_map(|g| match g {
+            0 => Some(MotionMode::Rapid),
+            1 => Some(MotionMode::Linear),
+            2 => Some(MotionMode::ClockwiseArc),
+            3 => Some(MotionMode::CounterClockwiseArc),
+            _ => None,
+        })
     }
 
-    pub fn normalized_x(&self) -> Option<f32> {
-        self.y
+    pub fn plane(&self) -> Option<Plane> {
+        self.g.iter().rev().find_map(|g| match g {
+            17 => Some(Plane::XY),
+            18 => Some(Plane::ZX),
+            19 => Some(Plane::YZ),
+            _ => None,
+        })
     }
 
-    pub fn normalized_y(&self) -> Option<f32> {
-        self.z
+    pub fn positioning(&self) -> Option<Positioning> {
+        self.g.iter().rev().find_map(|g| match g {
+            90 => Some(Positioning::Absolute),
+            91 => Some(Positioning::Relative),
+            _ => None,
+        })
     }
 
-    pub fn normalized_z(&self) -> Option<f32> {
-        self.x
+    pub fn units(&self) -> Option<Units> {
+        self.g.iter().rev().find_map(|g| match g {
+            20 => Some(Units::Inches),
+            21 => Some(Units::Millimeters),
+            _ => None,
+        })
+    }
+
+    pub fn has_axis_words(&self) -> bool {
+        [self.x, self.y, self.z, self.i, self.j, self.k]
+            .iter()
+            .any(Option::is_some)
+    }
+
+    /// Index of the selected work coordinate system, 0 for G54 up to 5 for G59.
+    pub fn work_offset(&self) -> Option<usize> {
+        self.g.iter().rev().find_map(|g| match g {
+            54..=59 => Some((g - 54) as usize),
+            _ => None,
+        })
+    }
+
+    pub fn has_g(&self, code: u32) -> bool {
+        self.g.contains(&code)
+    }
+
+    pub fn has_m(&self, code: u32) -> bool {
+        self.m.contains(&code)
     }
 }
 
@@ -81,31 +332,48 @@ impl GCodeInstruction {
 mod tests {
     use rstest::rstest;
 
-    use super::GCodeInstruction;
+    use super::{
+        split_comments, GCodeInstruction, MotionMode, ParseErrorKind, Plane, Positioning, Units,
+    };
 
     #[rstest]
-    #[case("N1G01", 1, None, None, None)]
-    #[case("  \n  N145G01   ", 145, None, None, None)]
-    #[case("N123G01X00.000", 123, Some(0.0), None, None)]
-    #[case("N123G01X00.000Y00.000", 123, Some(0.0), Some(0.0), None)]
-    #[case("N123G01X00.000Y00.000Z00.000", 123, Some(0.0), Some(0.0), Some(0.0))]
+    #[case("N1G01", Some(1), None, None, None)]
+    #[case("  \n  N145G01   ", Some(145), None, None, None)]
+    #[case("N123G01X00.000", Some(123), Some(0.0), None, None)]
+    #[case("N123G01X00.000Y00.000", Some(123), Some(0.0), Some(0.0), None)]
+    #[case(
+        "N123G01X00.000Y00.000Z00.000",
+        Some(123),
+        Some(0.0),
+        Some(0.0),
+        Some(0.0)
+    )]
     #[case(
         "N123G01X12.345Y21.555Z05.005",
-        123,
+        Some(123),
         Some(12.345),
         Some(21.555),
         Some(5.005)
     )]
-    #[case("N123G01X-12.345Y21.555Z-05.005", 123, Some(-12.345), Some(21.555), Some(-5.005))]
+    #[case(
+        "N123G01X-12.345Y21.555Z-05.005",
+        Some(123),
+        Some(-12.345),
+        Some(21.555),
+        Some(-5.005)
+    )]
+    #[case("N7 G01 Z-1.5 X2 Y3", Some(7), Some(2.0), Some(3.0), Some(-1.5))]
+    #[case("n8g1y4x-2", Some(8), Some(-2.0), Some(4.0), None)]
+    #[case("  G01X1", None, Some(1.0), None, None)]
     fn code_is_parsed(
         #[case] line: &str,
-        #[case] n: u32,
+        #[case] n: Option<u32>,
         #[case] x: Option<f32>,
         #[case] y: Option<f32>,
         #[case] z: Option<f32>,
     ) {
         let instruction = GCodeInstruction::parse(line);
-        assert!(instruction.is_some());
+        assert!(instruction.is_ok());
 
         let instruction = instruction.unwrap();
 
@@ -115,13 +383,124 @@ mod tests {
         assert_eq!(instruction.z(), z);
     }
 
+    #[rstest]
+    #[case("N1G00X1", Some(MotionMode::Rapid))]
+    #[case("N1G01X1", Some(MotionMode::Linear))]
+    #[case("N1G90G0X1", Some(MotionMode::Rapid))]
+    #[case("N1G02X1Y1I1", Some(MotionMode::ClockwiseArc))]
+    #[case("N1G3X1Y1R1", Some(MotionMode::CounterClockwiseArc))]
+    #[case("N1M03S12000", None)]
+    fn motion_mode_is_parsed(#[case] line: &str, #[case] motion_mode: Option<MotionMode>) {
+        let instruction = GCodeInstruction::parse(line).unwrap();
+
+        assert_eq!(instruction.motion_mode(), motion_mode);
+    }
+
+    #[test]
+    fn feed_spindle_and_tool_words_are_parsed() {
+        let instruction = GCodeInstruction::parse("N5F1200S18000T3M06G01").unwrap();
+
+        assert_eq!(instruction.f(), Some(1200.0));
+        assert_eq!(instruction.s(), Some(18000.0));
+        assert_eq!(instruction.t(), Some(3));
+        assert!(instruction.has_m(6));
+        assert!(instruction.has_g(1));
+    }
+
+    #[test]
+    fn positioning_and_units_are_parsed() {
+        let instruction = GCodeInstruction::parse("N2G20G91G01X1").unwrap();
+
+        assert_eq!(instruction.positioning(), Some(Positioning::Relative));
+        assert_eq!(instruction.units(), Some(Units::Inches));
+    }
+
+    #[test]
+    fn arc_words_are_parsed() {
+        let instruction = GCodeInstruction::parse("N9G18G02X1Z2I-1.5K0.5").unwrap();
+
+        assert_eq!(instruction.plane(), Some(Plane::ZX));
+        assert_eq!(instruction.i(), Some(-1.5));
+        assert_eq!(instruction.j(), None);
+        assert_eq!(instruction.k(), Some(0.5));
+        assert_eq!(instruction.r(), None);
+    }
+
     #[rstest]
     #[case("N1aG01")]
     #[case("")]
-    #[case("N12301X00.000Y00.000Z00.000")]
+    #[case("N1")]
     #[case("N123G01Xa00.000Y00b.000Z00.000")]
+    #[case("N1G01X1X2")]
+    #[case("N1G01#1")]
     fn code_cannot_be_parsed(#[case] line: &str) {
         let instruction = GCodeInstruction::parse(line);
-        assert!(instruction.is_none());
+        assert!(instruction.is_err());
+    }
+
+    #[rstest]
+    #[case("N1G01X1X2", 8, ParseErrorKind::DuplicateWord('X'))]
+    #[case("  N1", 3, ParseErrorKind::MissingCommand)]
+    #[case("N1G01#1", 6, ParseErrorKind::UnexpectedCharacter('#'))]
+    #[case("N1G01X1.2.3", 6, ParseErrorKind::InvalidValue('X', "1.2.3".to_string()))]
+    #[case("N1G04P", 6, ParseErrorKind::InvalidValue('P', "".to_string()))]
+    #[case("N1", 1, ParseErrorKind::MissingCommand)]
+    fn parse_error_is_located(
+        #[case] line: &str,
+        #[case] column: usize,
+        #[case] kind: ParseErrorKind,
+    ) {
+        let error = GCodeInstruction::parse(line).unwrap_err();
+
+        assert_eq!(error.column(), column);
+        assert_eq!(error.kind(), &kind);
+    }
+
+    #[test]
+    fn modal_continuation_is_parsed() {
+        let instruction = GCodeInstruction::parse("N12X5Y3F800").unwrap();
+
+        assert_eq!(instruction.motion_mode(), None);
+        assert_eq!(instruction.x(), Some(5.0));
+        assert_eq!(instruction.f(), Some(800.0));
+        assert!(instruction.has_axis_words());
+    }
+
+    #[rstest]
+    #[case("N1G04P1", vec![(6, 'P')])]
+    #[case("N2G43H1Z50", vec![(6, 'H')])]
+    #[case("N3G83Z-5Q1R2", vec![(9, 'Q')])]
+    fn unhandled_words_are_ignored(#[case] line: &str, #[case] ignored: Vec<(usize, char)>) {
+        let instruction = GCodeInstruction::parse(line).unwrap();
+
+        let ignored_words = instruction
+            .ignored_words()
+            .iter()
+            .map(|error| match error.kind() {
+                ParseErrorKind::IgnoredWord(letter) => (error.column(), *letter),
+                kind => panic!("unexpected {:?}", kind),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ignored_words, ignored);
+    }
+
+    #[rstest]
+    #[case("N1G01X1 (rough X2)", "N1G01X1           ", vec!["rough X2"])]
+    #[case("N1G01X1 ; finish Z-3", "N1G01X1 ", vec!["finish Z-3"])]
+    #[case("(a)N1G00(b);c", "   N1G00   ", vec!["a", "b", "c"])]
+    #[case("N1G00 ()", "N1G00   ", vec![])]
+    fn comments_are_split(#[case] line: &str, #[case] code: &str, #[case] comments: Vec<&str>) {
+        let (split_code, split_comments) = split_comments(line).unwrap();
+
+        assert_eq!(split_code, code);
+        assert_eq!(split_comments, comments);
+    }
+
+    #[test]
+    fn unterminated_comment_is_rejected() {
+        let error = split_comments("N1G01 (oops").unwrap_err();
+
+        assert_eq!(error.column(), 7);
+        assert_eq!(error.kind(), &ParseErrorKind::UnterminatedComment);
     }
 }
@@ -1,4 +1,4 @@
-use crate::vertex::Vertex;
+use crate::{axis_transform::AxisTransform, fixture::Fixture, vertex::Vertex};
 
 pub fn generate_block(size: (f32, f32, f32), resolution: (u32, u32, u32)) -> Vec<Vertex> {
     let top_normal = 0;
@@ -89,3 +89,82 @@ pub fn generate_block(size: (f32, f32, f32), resolution: (u32, u32, u32)) -> Vec
         })
         .collect()
 }
+
+/// Builds boxes for the fixtures, converted from machine to scene coordinates.
+pub fn generate_fixtures(fixtures: &[Fixture], axis_transform: &AxisTransform) -> Vec<Vertex> {
+    let top_normal = 0;
+    let bottom_normal = 1;
+    let right_normal = 2;
+    let left_normal = 3;
+    let front_normal = 4;
+    let back_normal = 5;
+    let no_coordinates = (0.0, 0.0);
+
+    fixtures
+        .iter()
+        .flat_map(|fixture| {
+            let first = axis_transform.to_scene(fixture.min());
+            let second = axis_transform.to_scene(fixture.max());
+            let min = (
+                first.0.min(second.0),
+                first.1.min(second.1),
+                first.2.min(second.2),
+            );
+            let max = (
+                first.0.max(second.0),
+                first.1.max(second.1),
+                first.2.max(second.2),
+            );
+
+            let a = (min.0, max.1, min.2);
+            let b = (max.0, max.1, min.2);
+            let c = (min.0, max.1, max.2);
+            let d = (max.0, max.1, max.2);
+            let e = (min.0, min.1, min.2);
+            let f = (max.0, min.1, min.2);
+            let g = (min.0, min.1, max.2);
+            let h = (max.0, min.1, max.2);
+            [
+                (b, top_normal),
+                (a, top_normal),
+                (c, top_normal),
+                (d, top_normal),
+                (b, top_normal),
+                (c, top_normal),
+                (e, bottom_normal),
+                (f, bottom_normal),
+                (g, bottom_normal),
+                (f, bottom_normal),
+                (h, bottom_normal),
+                (g, bottom_normal),
+                (a, front_normal),
+                (b, front_normal),
+                (e, front_normal),
+                (f, front_normal),
+                (e, front_normal),
+                (b, front_normal),
+                (h, left_normal),
+                (f, left_normal),
+                (d, left_normal),
+                (b, left_normal),
+                (d, left_normal),
+                (f, left_normal),
+                (d, back_normal),
+                (c, back_normal),
+                (g, back_normal),
+                (g, back_normal),
+                (h, back_normal),
+                (d, back_normal),
+                (c, right_normal),
+                (a, right_normal),
+                (g, right_normal),
+                (e, right_normal),
+                (g, right_normal),
+                (a, right_normal),
+            ]
+            .map(|(position, normal)| {
+                Vertex::from_tuples(position, no_coordinates, no_coordinates, normal)
+            })
+        })
+        .collect()
+}
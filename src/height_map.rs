@@ -2,77 +2,168 @@ use std::collections::HashSet;
 
 use glium::{glutin::surface::WindowSurface, Display, Rect, Texture2d};
 
+/// Column heights and highlights of the stock, indexed by x and then z, with the columns
+/// changed since the textures were last updated.
+#[derive(Debug, Clone)]
+pub struct HeightData {
+    heights: Vec<Vec<f32>>,
+    changed_indices: HashSet<(usize, usize)>,
+    highlights: Vec<Vec<f32>>,
+    changed_highlights: HashSet<(usize, usize)>,
+}
+
 pub struct HeightMap {
+    data: HeightData,
+    stock_heights: Vec<Vec<f32>>,
     texture: Texture2d,
-    data: Vec<Vec<f32>>,
-    changed_indices: HashSet<(usize, usize)>,
+    highlight_texture: Texture2d,
+}
+
+impl HeightData {
+    pub fn new(resolution: (u32, u32, u32), height: f32) -> Self {
+        Self::from_heights(vec![
+            vec![height; resolution.2 as usize];
+            resolution.0 as usize
+        ])
+    }
+
+    pub fn from_heights(heights: Vec<Vec<f32>>) -> Self {
+        let highlights = vec![vec![0.0; heights[0].len()]; heights.len()];
+
+        Self {
+            heights,
+            changed_indices: HashSet::new(),
+            highlights,
+            changed_highlights: HashSet::new(),
+        }
+    }
+
+    pub fn write(&mut self, index: (usize, usize), height: f32) {
+        self.heights[index.0][index.1] = height;
+        self.changed_indices.insert((index.0, index.1));
+    }
+
+    /// Marks the column so that the block drawer paints it, e.g. where a rapid move cut.
+    pub fn highlight(&mut self, index: (usize, usize)) {
+        self.highlights[index.0][index.1] = 1.0;
+        self.changed_highlights.insert(index);
+    }
+
+    pub fn get_height(&self, index: (usize, usize)) -> f32 {
+        self.heights[index.0][index.1]
+    }
+
+    pub fn is_highlighted(&self, index: (usize, usize)) -> bool {
+        self.highlights[index.0][index.1] > 0.0
+    }
+
+    pub fn heights(&self) -> &[Vec<f32>] {
+        &self.heights
+    }
 }
 
 impl HeightMap {
     pub fn new(resolution: (u32, u32, u32), height: f32, display: &Display<WindowSurface>) -> Self {
+        Self::from_data(HeightData::new(resolution, height), display)
+    }
+
+    /// Height map with the given column heights, indexed by x and then z.
+    pub fn from_heights(data: Vec<Vec<f32>>, display: &Display<WindowSurface>) -> Self {
+        Self::from_data(HeightData::from_heights(data), display)
+    }
+
+    fn from_data(data: HeightData, display: &Display<WindowSurface>) -> Self {
+        Self {
+            texture: Self::create_texture(&data.heights, display),
+            highlight_texture: Self::create_texture(&data.highlights, display),
+            stock_heights: data.heights.clone(),
+            data,
+        }
+    }
+
+    fn create_texture(data: &[Vec<f32>], display: &Display<WindowSurface>) -> Texture2d {
         let texture = Texture2d::empty_with_format(
             display,
             glium::texture::UncompressedFloatFormat::F32,
             glium::texture::MipmapsOption::NoMipmap,
-            resolution.2,
-            resolution.0,
+            data[0].len() as u32,
+            data.len() as u32,
         )
         .unwrap();
 
-        let data = vec![vec![height; resolution.2 as usize]; resolution.0 as usize];
-
         texture.write(
             Rect {
                 left: 0,
                 bottom: 0,
-                width: resolution.2,
-                height: resolution.0,
+                width: data[0].len() as u32,
+                height: data.len() as u32,
             },
-            data.clone(),
+            data.to_vec(),
         );
 
-        Self {
-            texture,
-            data,
-            changed_indices: HashSet::new(),
-        }
+        texture
     }
 
     pub fn get_texture(&self) -> &Texture2d {
         &self.texture
     }
 
-    pub fn write(&mut self, index: (usize, usize), height: f32) {
-        self.data[index.0][index.1] = height;
-        self.changed_indices.insert((index.0, index.1));
+    pub fn get_highlight_texture(&self) -> &Texture2d {
+        &self.highlight_texture
+    }
+
+    /// Column heights the map was created with, before anything was cut.
+    pub fn stock_heights(&self) -> &[Vec<f32>] {
+        &self.stock_heights
+    }
+
+    pub fn data_mut(&mut self) -> &mut HeightData {
+        &mut self.data
     }
 
     pub fn update_texture(&mut self) {
-        if self.changed_indices.is_empty() {
+        Self::write_changed(
+            &self.texture,
+            &self.data.heights,
+            &mut self.data.changed_indices,
+        );
+        Self::write_changed(
+            &self.highlight_texture,
+            &self.data.highlights,
+            &mut self.data.changed_highlights,
+        );
+    }
+
+    fn write_changed(
+        texture: &Texture2d,
+        data: &[Vec<f32>],
+        changed_indices: &mut HashSet<(usize, usize)>,
+    ) {
+        if changed_indices.is_empty() {
             return;
         }
 
-        let left = self.changed_indices.iter().map(|i| i.1).min().unwrap();
-        let bottom = self.changed_indices.iter().map(|i| i.0).min().unwrap();
-        let width = self.changed_indices.iter().map(|i| i.1).max().unwrap() - left + 1;
-        let height = self.changed_indices.iter().map(|i| i.0).max().unwrap() - bottom + 1;
+        let left = changed_indices.iter().map(|i| i.1).min().unwrap();
+        let bottom = changed_indices.iter().map(|i| i.0).min().unwrap();
+        let width = changed_indices.iter().map(|i| i.1).max().unwrap() - left + 1;
+        let height = changed_indices.iter().map(|i| i.0).max().unwrap() - bottom + 1;
 
-        self.texture.write(
+        texture.write(
             Rect {
                 left: left as u32,
                 bottom: bottom as u32,
                 width: width as u32,
                 height: height as u32,
             },
-            self.data[bottom..(bottom + height)]
+            data[bottom..(bottom + height)]
                 .iter()
                 .map(|v| v[left..(left + width)].to_vec())
                 .collect::<Vec<_>>(),
         );
-        self.changed_indices.clear();
+        changed_indices.clear();
     }
 
-    pub fn get_height(&self, index: (usize, usize)) -> f32 {
-        self.data[index.0][index.1]
+    pub fn heights(&self) -> &[Vec<f32>] {
+        self.data.heights()
     }
 }
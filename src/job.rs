@@ -0,0 +1,355 @@
+use std::path::Path;
+
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    g_code::{GCode, GCodeError},
+    g_code_executor::{ExecutionError, GCodeExecutor, RapidCollision},
+    milling_cutter::MillingCutter,
+    tool_library::ToolLibrary,
+};
+
+#[derive(Debug, Clone, Getters, Serialize, Deserialize)]
+pub struct StageDefinition {
+    program: String,
+    #[serde(default)]
+    cutter: Option<MillingCutter>,
+}
+
+/// A list of programs milled one after another on the same stock, e.g. roughing followed by
+/// finishing. Relative paths are resolved against the directory of the job file.
+#[derive(Debug, Clone, Getters, Serialize, Deserialize)]
+pub struct JobDefinition {
+    #[serde(default)]
+    tool_library: Option<String>,
+    stages: Vec<StageDefinition>,
+}
+
+#[derive(Debug)]
+pub enum StageStatus {
+    Pending,
+    Running,
+    Finished,
+    Skipped,
+    LoadFailed(GCodeError),
+    Failed(ExecutionError),
+}
+
+#[derive(Debug, Getters)]
+pub struct Stage {
+    program: String,
+    #[getter(skip)]
+    g_code: Option<GCode>,
+    status: StageStatus,
+    #[getter(copy)]
+    executed_instructions: usize,
+    warnings: Vec<ExecutionError>,
+    rapid_collisions: Vec<RapidCollision>,
+}
+
+#[derive(Debug, Getters)]
+pub struct Job {
+    stages: Vec<Stage>,
+}
+
+impl JobDefinition {
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let definition: JobDefinition =
+            serde_json::from_str(content).map_err(|error| error.to_string())?;
+        if definition.stages.is_empty() {
+            return Err("job has no stages".to_string());
+        }
+        for stage in definition.stages.iter() {
+            if let Some(cutter) = stage.cutter.as_ref() {
+                cutter
+                    .validate()
+                    .map_err(|error| format!("cutter of {}: {}", stage.program, error))?;
+            }
+        }
+        Ok(definition)
+    }
+}
+
+impl Job {
+    pub fn from_file(
+        file_path: &str,
+        skip_block_delete: bool,
+        tool_library: Option<&ToolLibrary>,
+    ) -> Result<Self, String> {
+        let content = std::fs::read_to_string(file_path).map_err(|error| error.to_string())?;
+        let definition = JobDefinition::from_json(&content)?;
+        let directory = Path::new(file_path).parent().unwrap_or(Path::new(""));
+
+        let job_library = match definition.tool_library.as_ref() {
+            Some(library_path) => {
+                let library_path = directory.join(library_path);
+                let content =
+                    std::fs::read_to_string(library_path).map_err(|error| error.to_string())?;
+                Some(ToolLibrary::from_json(&content)?)
+            }
+            None => None,
+        };
+        let tool_library = job_library.as_ref().or(tool_library);
+
+        let stages = definition
+            .stages
+            .into_iter()
+            .map(|stage| {
+                let program_path = directory.join(&stage.program);
+                let g_code = match program_path.to_str() {
+                    Some(program_path) => GCode::from_file(
+                        program_path,
+                        skip_block_delete,
+                        stage.cutter,
+                        tool_library,
+                    ),
+                    None => Err(GCodeError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "path is not valid UTF-8",
+                    ))),
+                };
+                Stage::new(stage.program, g_code)
+            })
+            .collect();
+
+        Ok(Self { stages })
+    }
+
+    /// Checks that every stage is loaded and none has run yet.
+    pub fn check_runnable(&self) -> Result<(), String> {
+        for (index, stage) in self.stages.iter().enumerate() {
+            match &stage.status {
+                StageStatus::Pending => {}
+                StageStatus::LoadFailed(error) => {
+                    return Err(format!(
+                        "stage {} ({}) cannot be loaded: {}",
+                        index + 1,
+                        stage.program,
+                        error
+                    ))
+                }
+                _ => return Err("the job has already been started".to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn current_stage(&self) -> Option<usize> {
+        self.stages
+            .iter()
+            .position(|stage| matches!(stage.status, StageStatus::Running))
+    }
+
+    /// Marks the next pending stage as running and returns its program.
+    pub fn start_next_stage(&mut self) -> Option<GCode> {
+        let stage = self
+            .stages
+            .iter_mut()
+            .find(|stage| matches!(stage.status, StageStatus::Pending))?;
+        stage.status = StageStatus::Running;
+        stage.g_code.clone()
+    }
+
+    /// Records the result of the running stage once `executor` has finished it and loads the
+    /// next stage. Returns the program that was loaded, if any.
+    pub fn advance(&mut self, executor: &mut GCodeExecutor) -> Option<GCode> {
+        let current_stage = self.current_stage()?;
+        if !executor.execution_finished() {
+            return None;
+        }
+
+        let stage = &mut self.stages[current_stage];
+        stage.executed_instructions = *executor.current_instruction();
+        stage.warnings = executor.warnings().clone();
+        stage.rapid_collisions = executor.rapid_collisions().clone();
+        match executor.error() {
+            Some(error) => {
+                stage.status = StageStatus::Failed(error.clone());
+                for stage in self.stages[(current_stage + 1)..].iter_mut() {
+                    stage.status = StageStatus::Skipped;
+                }
+                None
+            }
+            None => {
+                stage.status = StageStatus::Finished;
+                let g_code = self.start_next_stage()?;
+                executor.load(g_code.clone(), executor.limit_height_by_resolution());
+                Some(g_code)
+            }
+        }
+    }
+}
+
+impl Stage {
+    fn new(program: String, g_code: Result<GCode, GCodeError>) -> Self {
+        let (g_code, status) = match g_code {
+            Ok(g_code) => (Some(g_code), StageStatus::Pending),
+            Err(error) => (None, StageStatus::LoadFailed(error)),
+        };
+        Self {
+            program,
+            g_code,
+            status,
+            executed_instructions: 0,
+            warnings: vec![],
+            rapid_collisions: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Job, JobDefinition, Stage, StageStatus};
+    use crate::{
+        g_code::GCode,
+        g_code_executor::GCodeExecutor,
+        height_map::HeightData,
+        milling_cutter::{CutterShape, MillingCutter},
+    };
+
+    const RESOLUTION: (u32, u32, u32) = (100, 50, 100);
+    const SIZE: (f32, f32, f32) = (10.0, 5.0, 10.0);
+
+    fn job(programs: &[&str]) -> Job {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, None, None);
+        let stages = programs
+            .iter()
+            .enumerate()
+            .map(|(index, program)| {
+                let g_code = GCode::parse(program, None, Some(cutter.clone()), false, None);
+                Stage::new(format!("{}.nc", index + 1), g_code)
+            })
+            .collect();
+        Job { stages }
+    }
+
+    fn run(job: &mut Job) -> GCodeExecutor {
+        let mut executor =
+            GCodeExecutor::new(job.start_next_stage().unwrap(), RESOLUTION, SIZE, false);
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        loop {
+            while !executor.execution_finished() {
+                executor.execute_step(&mut height_data, 10.0);
+            }
+            if job.advance(&mut executor).is_none() {
+                return executor;
+            }
+        }
+    }
+
+    #[test]
+    fn job_definition_is_parsed() {
+        let definition = JobDefinition::from_json(
+            r#"{
+                "stages": [
+                    { "program": "1.k16" },
+                    {
+                        "program": "finish.nc",
+                        "cutter": { "shape": "Spherical", "diameter": 8.0 }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(definition.tool_library(), &None);
+        assert_eq!(definition.stages().len(), 2);
+        assert_eq!(definition.stages()[0].program(), "1.k16");
+        assert!(definition.stages()[0].cutter().is_none());
+        assert_eq!(
+            definition.stages()[1].cutter().as_ref().unwrap().shape(),
+            CutterShape::Spherical
+        );
+    }
+
+    #[test]
+    fn job_without_stages_is_rejected() {
+        assert!(JobDefinition::from_json(r#"{ "stages": [] }"#).is_err());
+    }
+
+    #[test]
+    fn invalid_stage_cutter_is_rejected() {
+        let content = r#"{
+            "stages": [{ "program": "1.nc", "cutter": { "shape": "Flat", "diameter": 0.0 } }]
+        }"#;
+
+        assert!(JobDefinition::from_json(content).is_err());
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut job = job(&["N1G00X0Y0Z50\nN2G00Z40\n", "N1G00Z60\n"]);
+        assert!(job.check_runnable().is_ok());
+
+        let g_code = job.start_next_stage().unwrap();
+        assert_eq!(g_code.instructions().len(), 2);
+        assert_eq!(job.current_stage(), Some(0));
+        assert!(matches!(job.stages()[1].status(), StageStatus::Pending));
+        assert!(job.check_runnable().is_err());
+
+        let mut executor = GCodeExecutor::new(g_code, RESOLUTION, SIZE, false);
+        assert!(job.advance(&mut executor).is_none());
+        assert_eq!(job.current_stage(), Some(0));
+
+        let mut height_data = HeightData::new(RESOLUTION, 2.5);
+        while !executor.execution_finished() {
+            executor.execute_step(&mut height_data, 10.0);
+        }
+        let next = job.advance(&mut executor).unwrap();
+
+        assert_eq!(next.instructions().len(), 1);
+        assert!(matches!(job.stages()[0].status(), StageStatus::Finished));
+        assert_eq!(job.stages()[0].executed_instructions(), 2);
+        assert_eq!(job.current_stage(), Some(1));
+        assert_eq!(*executor.current_instruction(), 0);
+        assert_eq!(executor.code().instructions().len(), 1);
+    }
+
+    #[test]
+    fn failed_stage_skips_remaining_stages() {
+        let mut job = job(&[
+            "N1G00X0Y0Z30\nN2G01Z20F100\nN3G00Z50\n",
+            "N1G00Z60\n",
+            "N1G00Z70\n",
+        ]);
+
+        let executor = run(&mut job);
+
+        assert!(executor.error().is_some());
+        let StageStatus::Failed(error) = job.stages()[0].status() else {
+            panic!("expected the first stage to fail");
+        };
+        assert_eq!(error.instruction(), 1);
+        assert_eq!(job.stages()[0].executed_instructions(), 1);
+        assert!(matches!(job.stages()[1].status(), StageStatus::Skipped));
+        assert!(matches!(job.stages()[2].status(), StageStatus::Skipped));
+        assert_eq!(job.current_stage(), None);
+    }
+
+    #[test]
+    fn stage_that_cannot_be_loaded_is_explained() {
+        let job = job(&["N1G00Z60\n", "N1G01X1.2.3\n"]);
+
+        let error = job.check_runnable().unwrap_err();
+
+        assert!(
+            error.starts_with("stage 2 (2.nc) cannot be loaded"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn rapid_collisions_are_kept_per_stage() {
+        let mut job = job(&["N1G00X-80Y0Z20\nN2G00X0\n", "N1G01Z50F500\n"]);
+
+        let executor = run(&mut job);
+
+        assert!(executor.rapid_collisions().is_empty());
+        assert!(matches!(job.stages()[0].status(), StageStatus::Finished));
+        assert_eq!(job.stages()[0].rapid_collisions().len(), 1);
+        assert_eq!(job.stages()[0].rapid_collisions()[0].instruction(), 1);
+        assert!(job.stages()[1].rapid_collisions().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use derive_getters::Getters;
+
+use crate::{
+    axis_transform::AxisTransform,
+    fixture::Fixture,
+    g_code_executor::START_POSITION,
+    machine_state::{MachineState, WORK_OFFSET_COUNT},
+    time_estimate::MotionLimits,
+};
+
+pub const DEFAULT_RAPID_RATE: f32 = 5000.0;
+
+/// How the stock is set up on the machine. All values are machine coordinates in mm.
+#[derive(Debug, Clone, Getters)]
+pub struct MachineSetup {
+    /// Where the tool starts and where G28 returns to.
+    #[getter(copy)]
+    home_position: (f32, f32, f32),
+    /// G54 to G59 offsets of the program zero.
+    work_offsets: [(f32, f32, f32); WORK_OFFSET_COUNT],
+    /// Height of the table or plate the stock is clamped to, if it is modelled. The tool tip
+    /// must stay above it everywhere, also away from the stock.
+    #[getter(copy)]
+    base_plate_height: Option<f32>,
+    fixtures: Vec<Fixture>,
+    #[getter(copy)]
+    axis_transform: AxisTransform,
+    /// Speed of rapid moves in mm/min, also used for moves without a programmed feed.
+    #[getter(copy)]
+    rapid_rate: f32,
+    #[getter(copy)]
+    motion_limits: MotionLimits,
+}
+
+impl Default for MachineSetup {
+    /// Setup with the home above the stock centre, program zero at the machine zero and no
+    /// base plate.
+    fn default() -> Self {
+        Self {
+            home_position: START_POSITION,
+            work_offsets: [(0.0, 0.0, 0.0); WORK_OFFSET_COUNT],
+            base_plate_height: None,
+            fixtures: vec![],
+            axis_transform: AxisTransform::default(),
+            rapid_rate: DEFAULT_RAPID_RATE,
+            motion_limits: MotionLimits::default(),
+        }
+    }
+}
+
+impl MachineSetup {
+    pub fn set_home_position(&mut self, home_position: (f32, f32, f32)) {
+        self.home_position = home_position;
+    }
+
+    pub fn set_work_offset(&mut self, index: usize, work_offset: (f32, f32, f32)) {
+        self.work_offsets[index] = work_offset;
+    }
+
+    pub fn set_base_plate_height(&mut self, base_plate_height: Option<f32>) {
+        self.base_plate_height = base_plate_height;
+    }
+
+    pub fn set_fixtures(&mut self, fixtures: Vec<Fixture>) {
+        self.fixtures = fixtures;
+    }
+
+    pub fn set_axis_transform(&mut self, axis_transform: AxisTransform) {
+        self.axis_transform = axis_transform;
+    }
+
+    pub fn set_rapid_rate(&mut self, rapid_rate: f32) {
+        self.rapid_rate = rapid_rate;
+    }
+
+    pub fn set_motion_limits(&mut self, motion_limits: MotionLimits) {
+        self.motion_limits = motion_limits;
+    }
+
+    /// Offset placing the program zero at the centre of the stock top.
+    pub fn stock_top_centre(block_size: (f32, f32, f32)) -> (f32, f32, f32) {
+        (0.0, 0.0, block_size.1 * 10.0 / 2.0)
+    }
+
+    /// Offset placing the program zero at the stock top corner with the lowest X and Y.
+    pub fn stock_top_corner(&self, block_size: (f32, f32, f32)) -> (f32, f32, f32) {
+        let corner = self
+            .axis_transform
+            .to_machine((block_size.0 / 2.0, 0.0, block_size.2 / 2.0));
+        (-corner.0.abs(), -corner.1.abs(), block_size.1 * 10.0 / 2.0)
+    }
+
+    /// State of the machine before a program starts: at home, with the offsets loaded.
+    pub fn initial_state(&self) -> MachineState {
+        MachineState::new(self.home_position).with_work_offsets(self.work_offsets)
+    }
+}
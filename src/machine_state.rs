@@ -0,0 +1,280 @@
+use derive_getters::Getters;
+
+use crate::{
+    arc::{interpolate_arc, is_valid_arc, ArcCenter},
+    g_code_instruction::{GCodeInstruction, MotionMode, Plane, Positioning, Units},
+};
+
+const MILLIMETERS_PER_INCH: f32 = 25.4;
+pub const WORK_OFFSET_COUNT: usize = 6;
+
+#[derive(Debug, Clone, Getters)]
+pub struct MachineState {
+    #[getter(copy)]
+    position: (f32, f32, f32),
+    #[getter(copy)]
+    motion_mode: MotionMode,
+    #[getter(copy)]
+    plane: Plane,
+    #[getter(copy)]
+    positioning: Positioning,
+    #[getter(copy)]
+    units: Units,
+    #[getter(copy)]
+    feed: Option<f32>,
+    #[getter(copy)]
+    spindle_speed: Option<f32>,
+    #[getter(copy)]
+    tool: Option<u32>,
+    #[getter(copy)]
+    home: (f32, f32, f32),
+    work_offsets: [(f32, f32, f32); WORK_OFFSET_COUNT],
+    /// Index of the active work offset, 0 for G54 up to 5 for G59.
+    #[getter(copy)]
+    work_offset: usize,
+    #[getter(copy)]
+    last_move_rapid: bool,
+    /// Whether the last move was an arc whose radius does not fit its end point.
+    #[getter(copy)]
+    last_arc_invalid: bool,
+}
+
+impl MachineState {
+    pub fn new(position: (f32, f32, f32)) -> Self {
+        Self {
+            position,
+            motion_mode: MotionMode::Rapid,
+            plane: Plane::XY,
+            positioning: Positioning::Absolute,
+            units: Units::Millimeters,
+            feed: None,
+            spindle_speed: None,
+            tool: None,
+            home: position,
+            work_offsets: [(0.0, 0.0, 0.0); WORK_OFFSET_COUNT],
+            work_offset: 0,
+            last_move_rapid: false,
+            last_arc_invalid: false,
+        }
+    }
+
+    pub fn with_home(mut self, home: (f32, f32, f32)) -> Self {
+        self.home = home;
+        self
+    }
+
+    pub fn with_work_offsets(mut self, work_offsets: [(f32, f32, f32); WORK_OFFSET_COUNT]) -> Self {
+        self.work_offsets = work_offsets;
+        self
+    }
+
+    /// Position in the active work coordinate system.
+    pub fn program_position(&self) -> (f32, f32, f32) {
+        let offset = self.work_offsets[self.work_offset];
+        (
+            self.position.0 - offset.0,
+            self.position.1 - offset.1,
+            self.position.2 - offset.2,
+        )
+    }
+
+    /// Applies the modal words of `instruction` and returns the points (in machine millimetres)
+    /// the tool passes through, ending at the new position. Absolute coordinates are relative
+    /// to the active work offset. G28 moves through the given point to the home position.
+    pub fn apply(
+        &mut self,
+        instruction: &GCodeInstruction,
+        chord_tolerance: f32,
+    ) -> Vec<(f32, f32, f32)> {
+        self.units = instruction.units().unwrap_or(self.units);
+        self.positioning = instruction.positioning().unwrap_or(self.positioning);
+        self.motion_mode = instruction.motion_mode().unwrap_or(self.motion_mode);
+        self.plane = instruction.plane().unwrap_or(self.plane);
+        self.feed = instruction
+            .f()
+            .map(|f| self.to_millimeters(f))
+            .or(self.feed);
+        self.spindle_speed = instruction.s().or(self.spindle_speed);
+        self.tool = instruction.t().or(self.tool);
+        self.work_offset = instruction.work_offset().unwrap_or(self.work_offset);
+        self.last_move_rapid = false;
+        self.last_arc_invalid = false;
+
+        if instruction.has_g(28) {
+            return self.return_home(instruction);
+        }
+
+        if !instruction.has_axis_words() {
+            return Vec::new();
+        }
+
+        let start = self.position;
+        let offset = self.work_offsets[self.work_offset];
+        let end = (
+            self.axis_target(start.0, offset.0, instruction.x()),
+            self.axis_target(start.1, offset.1, instruction.y()),
+            self.axis_target(start.2, offset.2, instruction.z()),
+        );
+        self.position = end;
+        self.last_move_rapid = self.motion_mode == MotionMode::Rapid;
+
+        match self.motion_mode {
+            MotionMode::Rapid | MotionMode::Linear => vec![end],
+            MotionMode::ClockwiseArc | MotionMode::CounterClockwiseArc => {
+                let center = match instruction.r() {
+                    Some(r) => ArcCenter::Radius(self.to_millimeters(r)),
+                    None => ArcCenter::Offset(
+                        self.to_millimeters(instruction.i().unwrap_or(0.0)),
+                        self.to_millimeters(instruction.j().unwrap_or(0.0)),
+                        self.to_millimeters(instruction.k().unwrap_or(0.0)),
+                    ),
+                };
+                self.last_arc_invalid = !is_valid_arc(start, end, center, self.plane);
+                interpolate_arc(
+                    start,
+                    end,
+                    center,
+                    self.motion_mode == MotionMode::ClockwiseArc,
+                    self.plane,
+                    chord_tolerance,
+                )
+            }
+        }
+    }
+
+    fn return_home(&mut self, instruction: &GCodeInstruction) -> Vec<(f32, f32, f32)> {
+        let start = self.position;
+        let offset = self.work_offsets[self.work_offset];
+        let intermediate = (
+            self.axis_target(start.0, offset.0, instruction.x()),
+            self.axis_target(start.1, offset.1, instruction.y()),
+            self.axis_target(start.2, offset.2, instruction.z()),
+        );
+
+        let all_axes =
+            instruction.x().is_none() && instruction.y().is_none() && instruction.z().is_none();
+        let home_axis = |word: Option<f32>, current: f32, home: f32| {
+            if all_axes || word.is_some() {
+                home
+            } else {
+                current
+            }
+        };
+        let end = (
+            home_axis(instruction.x(), intermediate.0, self.home.0),
+            home_axis(instruction.y(), intermediate.1, self.home.1),
+            home_axis(instruction.z(), intermediate.2, self.home.2),
+        );
+
+        self.position = end;
+        self.last_move_rapid = true;
+        [intermediate, end]
+            .into_iter()
+            .filter(|&point| point != start)
+            .collect()
+    }
+
+    fn axis_target(&self, current: f32, offset: f32, word: Option<f32>) -> f32 {
+        match (word, self.positioning) {
+            (Some(value), Positioning::Absolute) => self.to_millimeters(value) + offset,
+            (Some(value), Positioning::Relative) => current + self.to_millimeters(value),
+            (None, _) => current,
+        }
+    }
+
+    fn to_millimeters(&self, value: f32) -> f32 {
+        match self.units {
+            Units::Millimeters => value,
+            Units::Inches => value * MILLIMETERS_PER_INCH,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MachineState;
+    use crate::g_code_instruction::{GCodeInstruction, MotionMode, Positioning, Units};
+
+    fn run(lines: &[&str]) -> MachineState {
+        let mut state = MachineState::new((0.0, 0.0, 0.0));
+        for line in lines {
+            state.apply(&GCodeInstruction::parse(line).unwrap(), 0.01);
+        }
+        state
+    }
+
+    #[test]
+    fn relative_moves_accumulate() {
+        let state = run(&["N1G01X10Y5", "N2G91X1Y-1Z-2", "N3G01X1"]);
+
+        assert_eq!(state.positioning(), Positioning::Relative);
+        assert_eq!(state.position(), (12.0, 4.0, -2.0));
+    }
+
+    #[test]
+    fn absolute_positioning_is_restored() {
+        let state = run(&["N1G91G01X10", "N2G90X3"]);
+
+        assert_eq!(state.position(), (3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn inch_coordinates_and_feed_are_converted() {
+        let state = run(&["N1G20G01X1Y-0.5F10"]);
+
+        assert_eq!(state.units(), Units::Inches);
+        assert_eq!(state.position(), (25.4, -12.7, 0.0));
+        assert_eq!(state.feed(), Some(254.0));
+    }
+
+    #[test]
+    fn work_offsets_shift_absolute_moves() {
+        let mut state = MachineState::new((0.0, 0.0, 0.0)).with_work_offsets([
+            (0.0, 0.0, 0.0),
+            (-75.0, -75.0, 50.0),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+        ]);
+
+        for line in ["N1G55G01X10Y10Z0", "N2G91X5"] {
+            state.apply(&GCodeInstruction::parse(line).unwrap(), 0.01);
+        }
+
+        assert_eq!(state.work_offset(), 1);
+        assert_eq!(state.position(), (-60.0, -65.0, 50.0));
+        assert_eq!(state.program_position(), (15.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn g28_returns_home_through_intermediate_point() {
+        let mut state = MachineState::new((0.0, 0.0, 0.0)).with_home((0.0, 0.0, 200.0));
+        state.apply(&GCodeInstruction::parse("N1G01X10Y20Z-5").unwrap(), 0.01);
+
+        let points = state.apply(&GCodeInstruction::parse("N2G28Z10").unwrap(), 0.01);
+
+        assert_eq!(points, vec![(10.0, 20.0, 10.0), (10.0, 20.0, 200.0)]);
+        assert!(state.last_move_rapid());
+        assert_eq!(state.motion_mode(), MotionMode::Linear);
+    }
+
+    #[test]
+    fn g28_without_axes_returns_all_axes_home() {
+        let mut state = MachineState::new((5.0, 5.0, 5.0)).with_home((0.0, 0.0, 200.0));
+
+        let points = state.apply(&GCodeInstruction::parse("N1G28").unwrap(), 0.01);
+
+        assert_eq!(points, vec![(0.0, 0.0, 200.0)]);
+    }
+
+    #[test]
+    fn instruction_without_axis_words_does_not_move() {
+        let mut state = MachineState::new((1.0, 2.0, 3.0));
+
+        let points = state.apply(&GCodeInstruction::parse("N1G02M03").unwrap(), 0.01);
+
+        assert!(points.is_empty());
+        assert_eq!(state.position(), (1.0, 2.0, 3.0));
+    }
+}
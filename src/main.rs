@@ -1,4 +1,9 @@
+pub mod arc;
+pub mod axis_transform;
 pub mod block_drawer;
+pub mod comparison;
+pub mod cutter_profile;
+pub mod fixture;
 pub mod g_code;
 pub mod g_code_drawer;
 pub mod g_code_executor;
@@ -6,28 +11,57 @@ pub mod g_code_executor_drawer;
 pub mod g_code_instruction;
 pub mod generate_block;
 pub mod height_map;
+pub mod job;
+pub mod machine_setup;
+pub mod machine_state;
+pub mod mesh_export;
 pub mod milling_cutter;
+pub mod stl_import;
+pub mod stock_snapshot;
+pub mod swept_volume;
 pub mod target_height_map;
+pub mod time_estimate;
+pub mod tool_library;
 pub mod vertex;
 
 use std::fs;
 
+use axis_transform::AxisTransform;
 use block_drawer::BlockDrawer;
 use chrono::Local;
+use comparison::ComparisonReport;
 use egui::{Color32, DragValue, ViewportId, Widget};
-use g_code::GCode;
+use fixture::FixtureSet;
+use g_code::{GCode, GCodeError};
 use g_code_drawer::GCodeDrawer;
-use g_code_executor::GCodeExecutor;
+use g_code_executor::{ErrorPolicy, ExecutionErrorKind, GCodeExecutor};
 use g_code_executor_drawer::GCodeExecutorDrawer;
-use generate_block::generate_block;
+use g_code_instruction::{MotionMode, Positioning, Units};
+use generate_block::{generate_block, generate_fixtures};
 use glium::Surface;
 use height_map::HeightMap;
+use job::{Job, StageStatus};
+use machine_setup::MachineSetup;
+use machine_state::WORK_OFFSET_COUNT;
+use mesh_export::{Mesh, MeshFormat};
+use milling_cutter::{CutterShape, MillingCutter};
 use nalgebra::{Matrix4, Point3, Vector3, Vector4};
 use rfd::FileDialog;
+use stl_import::{StlError, StlModel};
+use stock_snapshot::{program_name, ResumePoint, SnapshotError, StockSnapshot};
+use time_estimate::TimeEstimate;
 use vertex::SmallVertex;
 use winit::event::{self, ElementState, MouseButton};
 
-use crate::target_height_map::TargetHeightMap;
+use crate::{
+    cutter_profile::ToolHolder,
+    target_height_map::{TargetHeightMap, ToleranceBands},
+    tool_library::{Tool, ToolLibrary},
+};
+
+/// Longest machine time step per frame at 1x playback, so that a stalled frame does not make
+/// the tool jump.
+const MAX_FRAME_TIME: f32 = 0.1;
 
 fn main() {
     let width = 1600;
@@ -83,19 +117,56 @@ fn main() {
     let mut height_map = HeightMap::new(block_resolution, block_size.1 / 2.0, &display);
 
     let mut block_created = false;
+    let mut snapshot_error: Option<String> = None;
+    let mut resume_point: Option<ResumePoint> = None;
+    let mut resume_error: Option<String> = None;
+    let mut mesh_format = MeshFormat::BinaryStl;
+    let mut decimate_mesh = true;
+    let mut mesh_export_error: Option<String> = None;
+    let mut stl_error: Option<String> = None;
 
     let mut g_code_loaded = false;
     let mut g_code_executor: Option<GCodeExecutor> = None;
+    let mut g_code_error: Option<GCodeError> = None;
+    let mut job: Option<Job> = None;
+    let mut job_error: Option<String> = None;
     let mut g_code_vertices = glium::VertexBuffer::new(&display, &[]).unwrap();
     let g_code_drawer = GCodeDrawer::new(&display);
     let g_code_executor_drawer = GCodeExecutorDrawer::new(&display);
-    let mut milling_speed = 1u32;
+    let mut playback_speed = 1.0f32;
     let mut draw_g_code_lines = true;
     let mut max_cutter_immersion = 5f32;
+    let mut error_policy = ErrorPolicy::default();
+    let mut remove_material_on_rapid = false;
+    let mut machine_setup = MachineSetup::default();
+    let mut axis_transform_error: Option<String> = None;
+    let mut fixtures_error: Option<String> = None;
+    let mut fixture_vertices = glium::VertexBuffer::new(&display, &[]).unwrap();
+    let mut time_estimate: Option<TimeEstimate> = None;
+    let mut time_report_error: Option<String> = None;
     let mut limit_height_by_resolution = true;
+    let mut chord_tolerance = 0.01f32;
+    let mut skip_block_delete = true;
+    let mut tool_library: Option<ToolLibrary> = None;
+    let mut tool_library_error: Option<String> = None;
+    let mut override_cutter = false;
+    let mut cutter_shape = CutterShape::Flat;
+    let mut cutter_diameter = 10.0f32;
+    let mut cutter_cutting_length = 25.0f32;
+    let mut cutter_overall_length = 60.0f32;
+    let mut cutter_shank_diameter = 10.0f32;
+    let mut use_holder = false;
+    let mut holder_diameter = 32.0f32;
+    let mut holder_length = 50.0f32;
 
     let mut target_height_map = TargetHeightMap::default().to_texture(&display);
     let mut use_target_height_map = false;
+    let mut loaded_target: Option<TargetHeightMap> = None;
+    let mut resampled_target: Option<TargetHeightMap> = None;
+    let mut target_error: Option<String> = None;
+    let mut tolerance_bands = ToleranceBands::default();
+    let mut comparison: Option<ComparisonReport> = None;
+    let mut comparison_error: Option<String> = None;
 
     let mut previous_time = Local::now();
 
@@ -105,6 +176,7 @@ fn main() {
             let duration = current_time - previous_time;
             let duration_in_seconds = duration.num_microseconds().unwrap_or(1) as f64 / 1_000_000.0;
             let fps = 1.0 / duration_in_seconds;
+            let frame_seconds = (duration_in_seconds as f32).min(MAX_FRAME_TIME);
             previous_time = current_time;
 
             egui_glium.run(&window, |egui_ctx| {
@@ -166,6 +238,76 @@ fn main() {
                                 HeightMap::new(block_resolution, block_size.1 / 2.0, &display);
                             block_created = true;
                         }
+
+                        if ui.button("Load stock").clicked() {
+                            match load_stock_snapshot() {
+                                Some(Ok(snapshot)) => {
+                                    block_size = snapshot.block_size();
+                                    block_resolution = snapshot.resolution();
+                                    block = generate_block(block_size, block_resolution);
+                                    vertex_buffers = [
+                                        glium::VertexBuffer::new(
+                                            &display,
+                                            &block[0..block.len() / 2],
+                                        )
+                                        .unwrap(),
+                                        glium::VertexBuffer::new(
+                                            &display,
+                                            &block[block.len() / 2..],
+                                        )
+                                        .unwrap(),
+                                    ];
+                                    resume_point = Some(snapshot.resume_point());
+                                    resume_error = None;
+                                    height_map = HeightMap::from_heights(
+                                        snapshot.heights().clone(),
+                                        &display,
+                                    );
+                                    snapshot_error = None;
+                                    block_created = true;
+                                }
+                                Some(Err(error)) => snapshot_error = Some(error.to_string()),
+                                None => {}
+                            }
+                        }
+                        if let Some(error) = snapshot_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot load stock: {}", error));
+                        }
+
+                        if ui.button("Import stock from STL").clicked() {
+                            match load_stl() {
+                                Some(Ok(model)) => {
+                                    block = generate_block(block_size, block_resolution);
+                                    vertex_buffers = [
+                                        glium::VertexBuffer::new(
+                                            &display,
+                                            &block[0..block.len() / 2],
+                                        )
+                                        .unwrap(),
+                                        glium::VertexBuffer::new(
+                                            &display,
+                                            &block[block.len() / 2..],
+                                        )
+                                        .unwrap(),
+                                    ];
+                                    height_map = HeightMap::from_heights(
+                                        model.rasterize(
+                                            &machine_setup.axis_transform(),
+                                            block_size,
+                                            block_resolution,
+                                        ),
+                                        &display,
+                                    );
+                                    stl_error = None;
+                                    block_created = true;
+                                }
+                                Some(Err(error)) => stl_error = Some(error.to_string()),
+                                None => {}
+                            }
+                        }
+                        if let Some(error) = stl_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot import STL: {}", error));
+                        }
                     } else {
                         if ui.button("Reset").clicked() {
                             block_created = false;
@@ -173,70 +315,741 @@ fn main() {
                                 HeightMap::new(block_resolution, block_size.1 / 2.0, &display);
                             g_code_loaded = false;
                             g_code_executor = None;
+                            job = None;
+                            resume_point = None;
+                            resume_error = None;
+                        }
+
+                        if ui.button("Save stock").clicked() {
+                            let snapshot = StockSnapshot::new(
+                                block_size,
+                                block_resolution,
+                                g_code_executor
+                                    .as_ref()
+                                    .and_then(|executor| executor.code().program_number()),
+                                g_code_executor
+                                    .as_ref()
+                                    .map_or(0, |executor| executor.code().fingerprint()),
+                                g_code_executor
+                                    .as_ref()
+                                    .map_or(0, |executor| *executor.current_instruction()),
+                                height_map.heights().to_vec(),
+                            );
+                            snapshot_error = save_stock_snapshot(&snapshot)
+                                .and_then(Result::err)
+                                .map(|error| error.to_string());
+                        }
+                        if let Some(error) = snapshot_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot save stock: {}", error));
+                        }
+
+                        egui::CollapsingHeader::new("Export mesh").show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                for format in MeshFormat::ALL {
+                                    ui.selectable_value(
+                                        &mut mesh_format,
+                                        format,
+                                        format.to_string(),
+                                    );
+                                }
+                            });
+                            ui.checkbox(&mut decimate_mesh, "Merge flat regions");
+                            if ui.button("Export").clicked() {
+                                if let Some(path) = FileDialog::new()
+                                    .add_filter(mesh_format.to_string(), &[mesh_format.extension()])
+                                    .save_file()
+                                    .and_then(|path| path.to_str().map(str::to_string))
+                                {
+                                    let mesh = Mesh::from_heights(
+                                        height_map.heights(),
+                                        block_size,
+                                        decimate_mesh,
+                                    )
+                                    .to_machine(&machine_setup.axis_transform());
+                                    mesh_export_error = mesh
+                                        .save(&path, mesh_format)
+                                        .err()
+                                        .map(|error| error.to_string());
+                                }
+                            }
+                            if let Some(error) = mesh_export_error.as_ref() {
+                                ui.colored_label(
+                                    Color32::RED,
+                                    format!("Cannot export mesh: {}", error),
+                                );
+                            }
+                        });
+
+                        if ui.button("Load tool library").clicked() {
+                            match load_tool_library() {
+                                Some(Ok(library)) => {
+                                    tool_library = Some(library);
+                                    tool_library_error = None;
+                                }
+                                Some(Err(error)) => tool_library_error = Some(error),
+                                None => {}
+                            }
+                        }
+                        if let Some(library) = tool_library.as_ref() {
+                            ui.label(format!("Tool library: {} tool(s)", library.tools().len()));
+                        }
+                        if let Some(error) = tool_library_error.as_ref() {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("Cannot load tool library: {}", error),
+                            );
+                        }
+
+                        let mut setup_changed = false;
+                        if ui.button("Load fixtures").clicked() {
+                            match load_fixtures() {
+                                Some(Ok(set)) => {
+                                    fixture_vertices = glium::VertexBuffer::new(
+                                        &display,
+                                        &generate_fixtures(
+                                            set.fixtures(),
+                                            &machine_setup.axis_transform(),
+                                        ),
+                                    )
+                                    .unwrap();
+                                    machine_setup.set_fixtures(set.fixtures().clone());
+                                    fixtures_error = None;
+                                    setup_changed = true;
+                                }
+                                Some(Err(error)) => fixtures_error = Some(error),
+                                None => {}
+                            }
+                        }
+                        if !machine_setup.fixtures().is_empty() {
+                            ui.label(format!("Fixtures: {}", machine_setup.fixtures().len()));
+                        }
+                        if let Some(error) = fixtures_error.as_ref() {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("Cannot load fixtures: {}", error),
+                            );
+                        }
+                        egui::CollapsingHeader::new("Machine setup").show(ui, |ui| {
+                            let mut has_base_plate = machine_setup.base_plate_height().is_some();
+                            let mut base_plate_height = machine_setup
+                                .base_plate_height()
+                                .unwrap_or(-block_size.1 * 10.0 / 2.0);
+                            ui.horizontal(|ui| {
+                                let toggled = ui.checkbox(&mut has_base_plate, "Base plate Z: ").changed();
+                                let changed = ui
+                                    .add_enabled(
+                                        has_base_plate,
+                                        DragValue::new(&mut base_plate_height).speed(0.1),
+                                    )
+                                    .changed();
+                                if toggled || changed {
+                                    machine_setup.set_base_plate_height(
+                                        has_base_plate.then_some(base_plate_height),
+                                    );
+                                    setup_changed = true;
+                                }
+                                ui.label("mm");
+                            });
+
+                            let axis_transform = machine_setup.axis_transform();
+                            let mut scene_x = axis_transform.scene_x();
+                            let mut scene_z = axis_transform.scene_z();
+                            let x_changed = axis_selector(ui, "Scene x axis", &mut scene_x);
+                            let z_changed = axis_selector(ui, "Scene z axis", &mut scene_z);
+                            if x_changed || z_changed {
+                                match AxisTransform::new(scene_x, scene_z) {
+                                    Ok(axis_transform) => {
+                                        machine_setup.set_axis_transform(axis_transform);
+                                        fixture_vertices = glium::VertexBuffer::new(
+                                            &display,
+                                            &generate_fixtures(
+                                                machine_setup.fixtures(),
+                                                &axis_transform,
+                                            ),
+                                        )
+                                        .unwrap();
+                                        axis_transform_error = None;
+                                        setup_changed = true;
+                                    }
+                                    Err(error) => axis_transform_error = Some(error),
+                                }
+                            }
+                            ui.label(format!("Axes: {}", machine_setup.axis_transform()));
+                            if let Some(error) = axis_transform_error.as_ref() {
+                                ui.colored_label(Color32::RED, error);
+                            }
+
+                            let mut rapid_rate = machine_setup.rapid_rate();
+                            ui.horizontal(|ui| {
+                                ui.label("Rapid rate: ");
+                                if DragValue::new(&mut rapid_rate)
+                                    .clamp_range(1.0..=100000.0)
+                                    .speed(10.0)
+                                    .ui(ui)
+                                    .changed()
+                                {
+                                    machine_setup.set_rapid_rate(rapid_rate);
+                                    setup_changed = true;
+                                }
+                                ui.label("mm/min");
+                            });
+
+                            let mut motion_limits = machine_setup.motion_limits();
+                            let mut acceleration = motion_limits.acceleration();
+                            if axis_limits_editor(
+                                ui,
+                                "Acceleration (mm/s²)",
+                                &mut acceleration,
+                                10.0,
+                            ) && motion_limits.set_acceleration(acceleration).is_ok()
+                            {
+                                setup_changed = true;
+                            }
+                            let mut jerk = motion_limits.jerk();
+                            if axis_limits_editor(ui, "Jerk (mm/s³)", &mut jerk, 100.0)
+                                && motion_limits.set_jerk(jerk).is_ok()
+                            {
+                                setup_changed = true;
+                            }
+                            let mut junction_deviation = motion_limits.junction_deviation();
+                            ui.horizontal(|ui| {
+                                ui.label("Junction deviation: ");
+                                if DragValue::new(&mut junction_deviation)
+                                    .clamp_range(0.001..=1.0)
+                                    .speed(0.001)
+                                    .ui(ui)
+                                    .changed()
+                                    && motion_limits
+                                        .set_junction_deviation(junction_deviation)
+                                        .is_ok()
+                                {
+                                    setup_changed = true;
+                                }
+                                ui.label("mm");
+                            });
+                            machine_setup.set_motion_limits(motion_limits);
+
+                            let mut home_position = machine_setup.home_position();
+                            if position_editor(ui, "Home", &mut home_position) {
+                                machine_setup.set_home_position(home_position);
+                                setup_changed = true;
+                            }
+
+                            for index in 0..WORK_OFFSET_COUNT {
+                                let mut work_offset = machine_setup.work_offsets()[index];
+                                let label = format!("G{}", 54 + index);
+                                if position_editor(ui, &label, &mut work_offset) {
+                                    machine_setup.set_work_offset(index, work_offset);
+                                    setup_changed = true;
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.button("G54 at stock top centre").clicked() {
+                                    machine_setup.set_work_offset(
+                                        0,
+                                        MachineSetup::stock_top_centre(block_size),
+                                    );
+                                    setup_changed = true;
+                                }
+                                if ui.button("G54 at stock corner").clicked() {
+                                    machine_setup.set_work_offset(
+                                        0,
+                                        machine_setup.stock_top_corner(block_size),
+                                    );
+                                    setup_changed = true;
+                                }
+                            });
+                        });
+                        if setup_changed {
+                            time_estimate = None;
+                            if let Some(g_code_executor) = g_code_executor.as_mut() {
+                                g_code_executor.set_machine_setup(machine_setup.clone());
+                                g_code_vertices = glium::VertexBuffer::new(
+                                    &display,
+                                    &path_vertices(
+                                        g_code_executor.code(),
+                                        &machine_setup,
+                                        chord_tolerance,
+                                    ),
+                                )
+                                .unwrap();
+                            }
+                        }
+
+                        ui.checkbox(&mut override_cutter, "Override cutter");
+                        if override_cutter {
+                            egui::ComboBox::from_label("Cutter type")
+                                .selected_text(cutter_shape_name(cutter_shape))
+                                .show_ui(ui, |ui| {
+                                    for shape in [
+                                        CutterShape::Flat,
+                                        CutterShape::Spherical,
+                                        CutterShape::Toroidal { corner_radius: 1.0 },
+                                        CutterShape::Conical {
+                                            tip_angle: 90.0,
+                                            tip_diameter: 0.0,
+                                        },
+                                    ] {
+                                        let selected = std::mem::discriminant(&cutter_shape)
+                                            == std::mem::discriminant(&shape);
+                                        if ui
+                                            .selectable_label(selected, cutter_shape_name(shape))
+                                            .clicked()
+                                            && !selected
+                                        {
+                                            cutter_shape = shape;
+                                        }
+                                    }
+                                });
+                            match &mut cutter_shape {
+                                CutterShape::Toroidal { corner_radius } => {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Corner radius: ");
+                                        DragValue::new(corner_radius)
+                                            .clamp_range(0.1..=cutter_diameter / 2.0)
+                                            .speed(0.1)
+                                            .ui(ui);
+                                        ui.label("mm");
+                                    });
+                                }
+                                CutterShape::Conical {
+                                    tip_angle,
+                                    tip_diameter,
+                                } => {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Tip angle: ");
+                                        DragValue::new(tip_angle).clamp_range(10.0..=170.0).ui(ui);
+                                        ui.label("°");
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.label("Tip diameter: ");
+                                        DragValue::new(tip_diameter)
+                                            .clamp_range(0.0..=cutter_diameter * 0.9)
+                                            .speed(0.1)
+                                            .ui(ui);
+                                        ui.label("mm");
+                                    });
+                                }
+                                CutterShape::Flat | CutterShape::Spherical => {}
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Diameter: ");
+                                DragValue::new(&mut cutter_diameter)
+                                    .clamp_range(0.1..=50.0)
+                                    .speed(0.1)
+                                    .ui(ui);
+                                ui.label("mm");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Cutting length: ");
+                                DragValue::new(&mut cutter_cutting_length)
+                                    .clamp_range(0.1..=200.0)
+                                    .speed(0.1)
+                                    .ui(ui);
+                                ui.label("mm");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Overall length: ");
+                                DragValue::new(&mut cutter_overall_length)
+                                    .clamp_range(0.1..=300.0)
+                                    .speed(0.1)
+                                    .ui(ui);
+                                ui.label("mm");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Shank diameter: ");
+                                DragValue::new(&mut cutter_shank_diameter)
+                                    .clamp_range(0.1..=50.0)
+                                    .speed(0.1)
+                                    .ui(ui);
+                                ui.label("mm");
+                            });
+                            ui.checkbox(&mut use_holder, "Holder");
+                            if use_holder {
+                                ui.horizontal(|ui| {
+                                    ui.label("Holder diameter: ");
+                                    DragValue::new(&mut holder_diameter)
+                                        .clamp_range(1.0..=200.0)
+                                        .speed(0.1)
+                                        .ui(ui);
+                                    ui.label("mm");
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Holder length: ");
+                                    DragValue::new(&mut holder_length)
+                                        .clamp_range(1.0..=300.0)
+                                        .speed(0.1)
+                                        .ui(ui);
+                                    ui.label("mm");
+                                });
+                            }
                         }
 
                         if ui.button("Load code").clicked() {
-                            let g_code = load_g_code();
-
-                            if g_code.is_some() {
-                                g_code_loaded = true;
-                                let mut vertices: Vec<SmallVertex> = Vec::new();
-                                for instruction in g_code.clone().unwrap().instructions() {
-                                    let x = instruction
-                                        .normalized_x()
-                                        .unwrap_or_else(|| vertices.last().unwrap().position()[0]);
-                                    let y = instruction
-                                        .normalized_y()
-                                        .unwrap_or_else(|| vertices.last().unwrap().position()[1]);
-                                    let z = instruction
-                                        .normalized_z()
-                                        .unwrap_or_else(|| vertices.last().unwrap().position()[2]);
-                                    vertices.push(SmallVertex::new([x, y, z]));
-                                }
-                                g_code_vertices =
-                                    glium::VertexBuffer::new(&display, &vertices).unwrap();
-
-                                if g_code_executor.is_some() {
-                                    let g_code_executor = g_code_executor.as_mut().unwrap();
-                                    g_code_executor
-                                        .load(g_code.unwrap(), limit_height_by_resolution);
+                            g_code_error = None;
+                            let cutter_override = override_cutter.then(|| {
+                                let cutter = MillingCutter::new(
+                                    cutter_shape,
+                                    cutter_diameter,
+                                    Some(cutter_cutting_length),
+                                    Some(cutter_overall_length),
+                                )
+                                .with_shank_diameter(cutter_shank_diameter);
+                                if use_holder {
+                                    cutter.with_holder(ToolHolder::new(
+                                        holder_diameter,
+                                        holder_length,
+                                    ))
                                 } else {
-                                    g_code_executor = Some(GCodeExecutor::new(
-                                        g_code.unwrap(),
-                                        block_resolution,
-                                        block_size,
-                                        limit_height_by_resolution,
-                                    ));
+                                    cutter
+                                }
+                            });
+                            match load_g_code(
+                                skip_block_delete,
+                                cutter_override,
+                                tool_library.as_ref(),
+                            ) {
+                                Some(Ok(g_code)) => match resume_point
+                                    .map(|point| point.instruction_for(&g_code))
+                                    .transpose()
+                                {
+                                    Err(error) => resume_error = Some(error),
+                                    Ok(resume_instruction) => {
+                                        job = None;
+                                        g_code_loaded = true;
+                                        time_estimate = None;
+                                        resume_point = None;
+                                        resume_error = None;
+                                        g_code_vertices = glium::VertexBuffer::new(
+                                            &display,
+                                            &path_vertices(
+                                                &g_code,
+                                                &machine_setup,
+                                                chord_tolerance,
+                                            ),
+                                        )
+                                        .unwrap();
+
+                                        load_into_executor(
+                                            &mut g_code_executor,
+                                            g_code,
+                                            block_resolution,
+                                            block_size,
+                                            limit_height_by_resolution,
+                                            chord_tolerance,
+                                            &error_policy,
+                                            remove_material_on_rapid,
+                                            &machine_setup,
+                                        );
+                                        if let (Some(instruction), Some(g_code_executor)) =
+                                            (resume_instruction, g_code_executor.as_mut())
+                                        {
+                                            g_code_executor.resume_from(instruction);
+                                        }
+                                    }
+                                },
+                                Some(Err(error)) => g_code_error = Some(error),
+                                None => {}
+                            }
+                        }
+                        if let Some(point) = resume_point {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "Next program resumes stock of {} at instruction {}",
+                                    program_name(point.program_number()),
+                                    point.instruction()
+                                ));
+                                if ui.button("Start from the beginning").clicked() {
+                                    resume_point = None;
+                                    resume_error = None;
+                                }
+                            });
+                        }
+                        if let Some(error) = resume_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot resume: {}", error));
+                        }
+
+                        if ui.button("Load job").clicked() {
+                            job_error = None;
+                            match load_job(skip_block_delete, tool_library.as_ref()) {
+                                Some(Ok(mut new_job)) => {
+                                    if let Err(error) = new_job.check_runnable() {
+                                        job_error = Some(format!("Cannot start job: {}", error));
+                                    } else if let Some(g_code) = new_job.start_next_stage() {
+                                        g_code_loaded = true;
+                                        time_estimate = None;
+                                        g_code_vertices = glium::VertexBuffer::new(
+                                            &display,
+                                            &path_vertices(
+                                                &g_code,
+                                                &machine_setup,
+                                                chord_tolerance,
+                                            ),
+                                        )
+                                        .unwrap();
+
+                                        load_into_executor(
+                                            &mut g_code_executor,
+                                            g_code,
+                                            block_resolution,
+                                            block_size,
+                                            limit_height_by_resolution,
+                                            chord_tolerance,
+                                            &error_policy,
+                                            remove_material_on_rapid,
+                                            &machine_setup,
+                                        );
+                                    }
+                                    job = Some(new_job);
+                                }
+                                Some(Err(error)) => {
+                                    job_error = Some(format!("Cannot load job: {}", error))
+                                }
+                                None => {}
+                            }
+                        }
+
+                        if let Some(error) = job_error.as_ref() {
+                            ui.colored_label(Color32::RED, error);
+                        }
+
+                        if let Some(job) = job.as_ref() {
+                            for (index, stage) in job.stages().iter().enumerate() {
+                                let text = match stage.status() {
+                                    StageStatus::Pending => "pending".to_string(),
+                                    StageStatus::Running => "running".to_string(),
+                                    StageStatus::Finished => format!(
+                                        "finished ({} instructions, {} warnings, {} rapid collisions)",
+                                        stage.executed_instructions(),
+                                        stage.warnings().len(),
+                                        stage.rapid_collisions().len()
+                                    ),
+                                    StageStatus::Skipped => "skipped".to_string(),
+                                    StageStatus::LoadFailed(error) => {
+                                        format!("cannot load: {}", error)
+                                    }
+                                    StageStatus::Failed(error) => format!(
+                                        "failed after {} instructions: {}",
+                                        stage.executed_instructions(),
+                                        error
+                                    ),
+                                };
+                                let color = match stage.status() {
+                                    StageStatus::LoadFailed(_) | StageStatus::Failed(_) => {
+                                        Color32::RED
+                                    }
+                                    _ => ui.visuals().text_color(),
+                                };
+                                ui.colored_label(
+                                    color,
+                                    format!("{}. {}: {}", index + 1, stage.program(), text),
+                                );
+                                if let StageStatus::LoadFailed(GCodeError::InvalidLines(errors)) =
+                                    stage.status()
+                                {
+                                    for line_error in errors {
+                                        ui.colored_label(
+                                            Color32::RED,
+                                            format!("    {}", line_error),
+                                        );
+                                    }
+                                }
+                                for collision in stage.rapid_collisions() {
+                                    ui.colored_label(
+                                        Color32::from_rgb(255, 128, 0),
+                                        format!("    {}", collision),
+                                    );
                                 }
                             }
                         }
 
+                        if let Some(error) = g_code_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot load code: {}", error));
+                            if let GCodeError::InvalidLines(errors) = error {
+                                egui::ScrollArea::vertical()
+                                    .max_height(150.0)
+                                    .show(ui, |ui| {
+                                        for line_error in errors {
+                                            ui.colored_label(Color32::RED, line_error.to_string());
+                                        }
+                                    });
+                            }
+                        }
+
                         if ui.button("Load target height map").clicked() {
-                            let thm = load_target_height_map();
-                            if let Some(thm) = thm {
-                                target_height_map = thm.to_texture(&display);
+                            match load_target_height_map() {
+                                Some(Ok(thm)) => {
+                                    loaded_target = Some(thm);
+                                    resampled_target = None;
+                                    target_error = None;
+                                }
+                                Some(Err(error)) => target_error = Some(error),
+                                None => {}
+                            }
+                        }
+                        if let Some(error) = target_error.as_ref() {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("Cannot load target height map: {}", error),
+                            );
+                        }
+                        if ui.button("Import target from STL").clicked() {
+                            match load_stl() {
+                                Some(Ok(model)) => {
+                                    let heights = model.rasterize(
+                                        &machine_setup.axis_transform(),
+                                        block_size,
+                                        block_resolution,
+                                    );
+                                    loaded_target = Some(TargetHeightMap::from_scene_heights(
+                                        &heights, block_size,
+                                    ));
+                                    resampled_target = None;
+                                    use_target_height_map = true;
+                                    stl_error = None;
+                                }
+                                Some(Err(error)) => stl_error = Some(error.to_string()),
+                                None => {}
                             }
                         }
+                        if let Some(error) = stl_error.as_ref() {
+                            ui.colored_label(Color32::RED, format!("Cannot import STL: {}", error));
+                        }
 
                         if ui.button("Instant").clicked() {
-                            if g_code_executor.is_some() {
-                                let g_code_executor = g_code_executor.as_mut().unwrap();
-                                while !g_code_executor.execution_finished() {
-                                    g_code_executor
-                                        .execute_step(&mut height_map, max_cutter_immersion);
+                            if let Some(g_code_executor) = g_code_executor.as_mut() {
+                                loop {
+                                    while !g_code_executor.execution_finished() {
+                                        g_code_executor.execute_step(
+                                            height_map.data_mut(),
+                                            max_cutter_immersion,
+                                        );
+                                    }
+
+                                    let Some(g_code) =
+                                        job.as_mut().and_then(|job| job.advance(g_code_executor))
+                                    else {
+                                        break;
+                                    };
+                                    time_estimate = None;
+                                    g_code_vertices = glium::VertexBuffer::new(
+                                        &display,
+                                        &path_vertices(&g_code, &machine_setup, chord_tolerance),
+                                    )
+                                    .unwrap();
                                 }
                             }
                         }
 
                         ui.horizontal(|ui| {
-                            ui.label("Speed: ");
-                            DragValue::new(&mut milling_speed)
-                                .clamp_range(1..=1000)
-                                .ui(ui);
+                            ui.label("Playback: ");
+                            for speed in [1.0, 10.0, 100.0] {
+                                ui.selectable_value(
+                                    &mut playback_speed,
+                                    speed,
+                                    format!("{}\u{d7}", speed),
+                                );
+                            }
                         });
 
+                        ui.checkbox(&mut skip_block_delete, "Skip block delete (/) lines");
                         ui.checkbox(&mut draw_g_code_lines, "Draw lines");
                         ui.checkbox(&mut use_target_height_map, "Use target height map");
+                        let mut gouge = tolerance_bands.gouge();
+                        ui.horizontal(|ui| {
+                            ui.label("Gouge tolerance: ");
+                            if DragValue::new(&mut gouge)
+                                .clamp_range(0.001..=10.0)
+                                .speed(0.01)
+                                .ui(ui)
+                                .changed()
+                            {
+                                tolerance_bands.set_gouge(gouge);
+                            }
+                            ui.label("mm");
+                        });
+                        let mut leftover = tolerance_bands.leftover();
+                        ui.horizontal(|ui| {
+                            ui.label("Leftover tolerance: ");
+                            if DragValue::new(&mut leftover)
+                                .clamp_range(0.001..=10.0)
+                                .speed(0.01)
+                                .ui(ui)
+                                .changed()
+                            {
+                                tolerance_bands.set_leftover(leftover);
+                            }
+                            ui.label("mm");
+                        });
+                        if let Some(target) = resampled_target.as_ref() {
+                            egui::CollapsingHeader::new("Compare with target").show(ui, |ui| {
+                                if ui.button("Compare").clicked() {
+                                    comparison = Some(ComparisonReport::new(
+                                        height_map.heights(),
+                                        height_map.stock_heights(),
+                                        target,
+                                        block_size,
+                                        tolerance_bands,
+                                    ));
+                                    comparison_error = None;
+                                }
+                                let Some(comparison) = comparison.as_ref() else {
+                                    return;
+                                };
+                                ui.label(format!(
+                                    "Gouge: max {:.3} mm, mean {:.3} mm, {:.1} mm³",
+                                    comparison.max_gouge(),
+                                    comparison.mean_gouge(),
+                                    comparison.gouge_volume()
+                                ));
+                                ui.label(format!(
+                                    "Leftover: max {:.3} mm, mean {:.3} mm, {:.1} mm³",
+                                    comparison.max_leftover(),
+                                    comparison.mean_leftover(),
+                                    comparison.leftover_volume()
+                                ));
+                                ui.label(format!(
+                                    "RMS deviation: {:.3} mm",
+                                    comparison.rms_deviation()
+                                ));
+                                ui.label(format!(
+                                    "Removed {:.1} mm³, remaining {:.1} mm³",
+                                    comparison.removed_volume(),
+                                    comparison.remaining_volume()
+                                ));
+                                ui.label(format!(
+                                    "Within -{} / +{} mm: {:.1}%",
+                                    comparison.tolerance_bands().gouge(),
+                                    comparison.tolerance_bands().leftover(),
+                                    comparison.within_tolerance()
+                                ));
+                                ui.horizontal(|ui| {
+                                    for (name, extension) in [("JSON", "json"), ("CSV", "csv")] {
+                                        if ui.button(format!("Save {}", name)).clicked() {
+                                            if let Some(path) = FileDialog::new()
+                                                .add_filter(name, &[extension])
+                                                .save_file()
+                                            {
+                                                let content = match extension {
+                                                    "json" => comparison.to_json(),
+                                                    _ => comparison.to_csv(),
+                                                };
+                                                comparison_error = fs::write(path, content)
+                                                    .err()
+                                                    .map(|error| error.to_string());
+                                            }
+                                        }
+                                    }
+                                });
+                                if let Some(error) = comparison_error.as_ref() {
+                                    ui.colored_label(
+                                        Color32::RED,
+                                        format!("Cannot save report: {}", error),
+                                    );
+                                }
+                            });
+                        }
 
                         ui.horizontal(|ui| {
                             ui.label("Max immersion: ");
@@ -246,22 +1059,229 @@ fn main() {
                             ui.label("cm");
                         });
 
+                        ui.label("Stop on:");
+                        for kind in ExecutionErrorKind::ALL {
+                            let mut stops = error_policy.stops(kind);
+                            if ui.checkbox(&mut stops, kind.to_string()).changed() {
+                                error_policy.set_stops(kind, stops);
+                                if let Some(g_code_executor) = g_code_executor.as_mut() {
+                                    g_code_executor.set_error_policy(error_policy.clone());
+                                }
+                            }
+                        }
+
+                        if ui
+                            .checkbox(&mut remove_material_on_rapid, "Cut material on rapid moves")
+                            .changed()
+                        {
+                            if let Some(g_code_executor) = g_code_executor.as_mut() {
+                                g_code_executor
+                                    .set_remove_material_on_rapid(remove_material_on_rapid);
+                            }
+                        }
+
                         ui.checkbox(
                             &mut limit_height_by_resolution,
                             "Limit height by resolution",
                         );
 
-                        if g_code_executor.is_some() {
-                            let g_code_executor = g_code_executor.as_ref().unwrap();
-                            if let Some(error) = g_code_executor.error() {
-                                match error {
-                                    g_code_executor::ExecutionError::TooDeepImmersion => {
-                                        ui.colored_label(Color32::RED, "Too deep cutter immersion")
+                        let chord_tolerance_changed = ui
+                            .horizontal(|ui| {
+                                ui.label("Arc tolerance: ");
+                                let response = DragValue::new(&mut chord_tolerance)
+                                    .clamp_range(0.001..=1.0)
+                                    .speed(0.001)
+                                    .ui(ui);
+                                ui.label("mm");
+                                response.changed()
+                            })
+                            .inner;
+                        if chord_tolerance_changed {
+                            time_estimate = None;
+                            if let Some(g_code_executor) = g_code_executor.as_mut() {
+                                g_code_executor
+                                    .set_chord_tolerance(chord_tolerance)
+                                    .expect("the arc tolerance editor only allows positive values");
+                                g_code_vertices = glium::VertexBuffer::new(
+                                    &display,
+                                    &path_vertices(
+                                        g_code_executor.code(),
+                                        &machine_setup,
+                                        chord_tolerance,
+                                    ),
+                                )
+                                .unwrap();
+                            }
+                        }
+
+                        if let Some(g_code_executor) = g_code_executor.as_ref() {
+                            ui.label(format!(
+                                "Motion: {}",
+                                match g_code_executor.machine_state().motion_mode() {
+                                    MotionMode::Rapid => "rapid (G00)",
+                                    MotionMode::Linear => "feed (G01)",
+                                    MotionMode::ClockwiseArc => "clockwise arc (G02)",
+                                    MotionMode::CounterClockwiseArc => {
+                                        "counterclockwise arc (G03)"
                                     }
-                                    g_code_executor::ExecutionError::VerticalCut => {
-                                        ui.colored_label(Color32::RED, "Vertical cut")
+                                }
+                            ));
+                            let active_tool = g_code_executor
+                                .active_tool()
+                                .and_then(|number| g_code_executor.code().tools().get(&number));
+                            if let Some(tool) = active_tool {
+                                ui.label(format!("Tool: T{} {}", tool.number(), tool.name()));
+                            }
+                            let cutter =
+                                active_tool.map_or(g_code_executor.code().cutter(), Tool::cutter);
+                            ui.label(format!(
+                                "Cutter: {} {:.2} mm",
+                                cutter_shape_name(cutter.shape()),
+                                cutter.diameter()
+                            ));
+                            if let Some(program_number) = g_code_executor.code().program_number() {
+                                ui.label(format!("Program: O{}", program_number));
+                            }
+                            let code_warnings = g_code_executor.code().warnings();
+                            if !code_warnings.is_empty() {
+                                egui::CollapsingHeader::new(format!(
+                                    "Ignored words: {}",
+                                    code_warnings.len()
+                                ))
+                                .show(ui, |ui| {
+                                    for warning in code_warnings {
+                                        ui.colored_label(Color32::YELLOW, warning.to_string());
                                     }
+                                });
+                            }
+                            if let Some(instruction) = g_code_executor
+                                .code()
+                                .instructions()
+                                .get(*g_code_executor.current_instruction())
+                            {
+                                ui.label(format!("Block: {}", instruction.label()));
+                                for comment in instruction.comments() {
+                                    ui.label(format!("({})", comment));
+                                }
+                            }
+                            let machine_state = g_code_executor.machine_state();
+                            ui.label(format!(
+                                "Positioning: {}, units: {}",
+                                match machine_state.positioning() {
+                                    Positioning::Absolute => "absolute (G90)",
+                                    Positioning::Relative => "incremental (G91)",
+                                },
+                                match machine_state.units() {
+                                    Units::Millimeters => "mm (G21)",
+                                    Units::Inches => "inch (G20)",
+                                }
+                            ));
+                            let program_position = machine_state.program_position();
+                            ui.label(format!(
+                                "G{}: X{:.3} Y{:.3} Z{:.3}",
+                                54 + machine_state.work_offset(),
+                                program_position.0,
+                                program_position.1,
+                                program_position.2
+                            ));
+                            ui.label(format!(
+                                "Time: {} elapsed, {} remaining",
+                                format_time(g_code_executor.elapsed_time()),
+                                format_time(g_code_executor.remaining_time())
+                            ));
+                            egui::CollapsingHeader::new("Cycle time estimate").show(ui, |ui| {
+                                if ui.button("Estimate").clicked() {
+                                    time_estimate = Some(TimeEstimate::new(
+                                        g_code_executor.code(),
+                                        machine_setup.initial_state(),
+                                        chord_tolerance,
+                                        machine_setup.rapid_rate(),
+                                        &machine_setup.motion_limits(),
+                                    ));
+                                    time_report_error = None;
+                                }
+                                let Some(time_estimate) = time_estimate.as_ref() else {
+                                    return;
                                 };
+                                ui.label(format!(
+                                    "Total: {}",
+                                    format_time(time_estimate.total_time())
+                                ));
+                                for (kind, breakdown) in time_estimate.by_kind() {
+                                    ui.label(format!(
+                                        "{}: {} ({} moves, {:.0} mm)",
+                                        kind,
+                                        format_time(breakdown.time()),
+                                        breakdown.moves(),
+                                        breakdown.length()
+                                    ));
+                                }
+                                for (tool, breakdown) in time_estimate.by_tool() {
+                                    ui.label(format!(
+                                        "{}: {} ({} moves, {:.0} mm)",
+                                        tool.map_or("No tool".to_string(), |tool| format!(
+                                            "T{}",
+                                            tool
+                                        )),
+                                        format_time(breakdown.time()),
+                                        breakdown.moves(),
+                                        breakdown.length()
+                                    ));
+                                }
+                                if ui.button("Save report").clicked() {
+                                    if let Some(path) =
+                                        FileDialog::new().add_filter("JSON", &["json"]).save_file()
+                                    {
+                                        time_report_error =
+                                            fs::write(path, time_estimate.to_json())
+                                                .err()
+                                                .map(|error| error.to_string());
+                                    }
+                                }
+                                if let Some(error) = time_report_error.as_ref() {
+                                    ui.colored_label(
+                                        Color32::RED,
+                                        format!("Cannot save report: {}", error),
+                                    );
+                                }
+                            });
+                            if let Some(feed) = machine_state.feed() {
+                                ui.label(format!("Feed: {:.0} mm/min", feed));
+                            }
+                            if let Some(spindle_speed) = machine_state.spindle_speed() {
+                                ui.label(format!("Spindle: {:.0} rpm", spindle_speed));
+                            }
+
+                            if let Some(error) = g_code_executor.error() {
+                                ui.colored_label(Color32::RED, error.to_string());
+                            }
+                            if !g_code_executor.rapid_collisions().is_empty() {
+                                ui.label(format!(
+                                    "Rapid collisions: {}",
+                                    g_code_executor.rapid_collisions().len()
+                                ));
+                                egui::ScrollArea::vertical()
+                                    .id_source("rapid_collisions")
+                                    .max_height(120.0)
+                                    .show(ui, |ui| {
+                                        for collision in g_code_executor.rapid_collisions() {
+                                            ui.colored_label(
+                                                Color32::from_rgb(255, 128, 0),
+                                                collision.to_string(),
+                                            );
+                                        }
+                                    });
+                            }
+                            if !g_code_executor.warnings().is_empty() {
+                                ui.label(format!("Warnings: {}", g_code_executor.warnings().len()));
+                                egui::ScrollArea::vertical()
+                                    .id_source("execution_warnings")
+                                    .max_height(120.0)
+                                    .show(ui, |ui| {
+                                        for warning in g_code_executor.warnings() {
+                                            ui.colored_label(Color32::YELLOW, warning.to_string());
+                                        }
+                                    });
                             }
                         }
                     }
@@ -272,6 +1292,18 @@ fn main() {
 
             window.request_redraw();
 
+            if let Some(loaded_target) = loaded_target.as_ref() {
+                let stale = resampled_target
+                    .as_ref()
+                    .is_none_or(|target| !target.matches_block(block_size, block_resolution));
+                if block_created && stale {
+                    let target = loaded_target.resample(block_size, block_resolution);
+                    target_height_map = target.to_texture(&display);
+                    resampled_target = Some(target);
+                    comparison = None;
+                }
+            }
+
             let mut target = display.draw();
 
             target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
@@ -286,6 +1318,8 @@ fn main() {
                 height_map.get_texture(),
                 &target_height_map,
                 use_target_height_map,
+                &tolerance_bands,
+                height_map.get_highlight_texture(),
             );
             block_drawer.draw(
                 &mut target,
@@ -297,8 +1331,21 @@ fn main() {
                 height_map.get_texture(),
                 &target_height_map,
                 use_target_height_map,
+                &tolerance_bands,
+                height_map.get_highlight_texture(),
             );
 
+            if !machine_setup.fixtures().is_empty() {
+                block_drawer.draw_fixtures(
+                    &mut target,
+                    &fixture_vertices,
+                    &perspective,
+                    &view,
+                    &drawing_parameters,
+                    -camera_distant * camera_direction,
+                );
+            }
+
             if g_code_loaded && draw_g_code_lines {
                 g_code_drawer.draw(
                     &mut target,
@@ -309,17 +1356,28 @@ fn main() {
                 );
             }
 
-            if g_code_executor.is_some() {
-                let g_code_executor = g_code_executor.as_mut().unwrap();
-                for _ in 0..milling_speed {
-                    g_code_executor.execute_step(&mut height_map, max_cutter_immersion);
+            if let Some(g_code_executor) = g_code_executor.as_mut() {
+                g_code_executor.advance(
+                    height_map.data_mut(),
+                    max_cutter_immersion,
+                    frame_seconds * playback_speed,
+                );
+
+                if let Some(g_code) = job.as_mut().and_then(|job| job.advance(g_code_executor)) {
+                    time_estimate = None;
+                    g_code_vertices = glium::VertexBuffer::new(
+                        &display,
+                        &path_vertices(&g_code, &machine_setup, chord_tolerance),
+                    )
+                    .unwrap();
                 }
 
                 g_code_executor_drawer.draw(
                     &mut target,
                     &perspective,
                     &view,
-                    g_code_executor.current_position().clone(),
+                    g_code_executor.tool_position(),
+                    g_code_executor.cutter_profile().as_ref(),
                     &drawing_parameters,
                 );
             }
@@ -377,33 +1435,29 @@ fn main() {
                             );
                         }
                     }
-                    WindowEvent::MouseInput { state, button, .. } => {
-                        if *button == MouseButton::Middle {
-                            camera_move_button_pressed = *state == ElementState::Pressed;
-                        }
+                    WindowEvent::MouseInput { state, button, .. }
+                        if *button == MouseButton::Middle =>
+                    {
+                        camera_move_button_pressed = *state == ElementState::Pressed;
                     }
                     WindowEvent::KeyboardInput {
                         device_id: _,
                         event,
                         is_synthetic: _,
+                    } if event.logical_key == "c" && event.state.is_pressed() && !event.repeat => {
+                        camera_move_button_pressed = !camera_move_button_pressed;
+                    }
+                    WindowEvent::MouseWheel {
+                        delta: event::MouseScrollDelta::LineDelta(_x, y),
+                        ..
                     } => {
-                        if event.logical_key == "c" && event.state.is_pressed() && !event.repeat {
-                            camera_move_button_pressed = !camera_move_button_pressed;
-                        }
+                        camera_distant += -y * 0.1;
+                        view = Matrix4::look_at_rh(
+                            &Point3::from_slice((-camera_distant * camera_direction).as_slice()),
+                            &Point3::new(0.0, 0.0, 0.0),
+                            &camera_up,
+                        );
                     }
-                    WindowEvent::MouseWheel { delta, .. } => match delta {
-                        event::MouseScrollDelta::LineDelta(_x, y) => {
-                            camera_distant += -y * 0.1;
-                            view = Matrix4::look_at_rh(
-                                &Point3::from_slice(
-                                    (-camera_distant * camera_direction).as_slice(),
-                                ),
-                                &Point3::new(0.0, 0.0, 0.0),
-                                &camera_up,
-                            );
-                        }
-                        _ => {}
-                    },
                     WindowEvent::TouchpadMagnify { delta, .. } => {
                         camera_distant -= *delta as f32 * 3.0;
                         view = Matrix4::look_at_rh(
@@ -429,14 +1483,215 @@ fn main() {
     });
 }
 
-fn load_g_code() -> Option<GCode> {
+fn load_g_code(
+    skip_block_delete: bool,
+    cutter_override: Option<MillingCutter>,
+    tool_library: Option<&ToolLibrary>,
+) -> Option<Result<GCode, GCodeError>> {
     let path = FileDialog::new().pick_file()?;
     let path = path.to_str()?;
-    GCode::from_file(path)
+    Some(GCode::from_file(
+        path,
+        skip_block_delete,
+        cutter_override,
+        tool_library,
+    ))
+}
+
+fn load_job(
+    skip_block_delete: bool,
+    tool_library: Option<&ToolLibrary>,
+) -> Option<Result<Job, String>> {
+    let path = FileDialog::new().pick_file()?;
+    let path = path.to_str()?;
+    Some(Job::from_file(path, skip_block_delete, tool_library))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_into_executor(
+    g_code_executor: &mut Option<GCodeExecutor>,
+    g_code: GCode,
+    block_resolution: (u32, u32, u32),
+    block_size: (f32, f32, f32),
+    limit_height_by_resolution: bool,
+    chord_tolerance: f32,
+    error_policy: &ErrorPolicy,
+    remove_material_on_rapid: bool,
+    machine_setup: &MachineSetup,
+) {
+    let g_code_executor = match g_code_executor.as_mut() {
+        Some(g_code_executor) => {
+            g_code_executor.load(g_code, limit_height_by_resolution);
+            g_code_executor
+        }
+        None => g_code_executor.insert(GCodeExecutor::new(
+            g_code,
+            block_resolution,
+            block_size,
+            limit_height_by_resolution,
+        )),
+    };
+    g_code_executor
+        .set_chord_tolerance(chord_tolerance)
+        .expect("the arc tolerance editor only allows positive values");
+    g_code_executor.set_error_policy(error_policy.clone());
+    g_code_executor.set_remove_material_on_rapid(remove_material_on_rapid);
+    g_code_executor.set_machine_setup(machine_setup.clone());
+}
+
+/// Formats machine time in seconds as `h:mm:ss`.
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Lets the user pick a signed machine axis (X or Y) for a horizontal scene axis.
+fn axis_selector(ui: &mut egui::Ui, label: &str, axis: &mut (usize, f32)) -> bool {
+    let name = |(index, sign): (usize, f32)| {
+        format!(
+            "{}{}",
+            if sign < 0.0 { "-" } else { "+" },
+            ["X", "Y"][index]
+        )
+    };
+    let mut changed = false;
+    egui::ComboBox::from_label(label)
+        .selected_text(name(*axis))
+        .show_ui(ui, |ui| {
+            for option in [(0, 1.0), (0, -1.0), (1, 1.0), (1, -1.0)] {
+                changed |= ui.selectable_value(axis, option, name(option)).changed();
+            }
+        });
+    changed
+}
+
+/// Shows X/Y/Z editors for a machine position in mm. Returns whether it was changed.
+fn position_editor(ui: &mut egui::Ui, label: &str, position: &mut (f32, f32, f32)) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}: ", label));
+        let x = DragValue::new(&mut position.0)
+            .prefix("X ")
+            .speed(0.1)
+            .ui(ui);
+        let y = DragValue::new(&mut position.1)
+            .prefix("Y ")
+            .speed(0.1)
+            .ui(ui);
+        let z = DragValue::new(&mut position.2)
+            .prefix("Z ")
+            .speed(0.1)
+            .ui(ui);
+        x.changed() || y.changed() || z.changed()
+    })
+    .inner
+}
+
+/// Edits positive per axis limits, e.g. accelerations.
+fn axis_limits_editor(
+    ui: &mut egui::Ui,
+    label: &str,
+    limits: &mut (f32, f32, f32),
+    speed: f32,
+) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}: ", label));
+        let x = DragValue::new(&mut limits.0)
+            .prefix("X ")
+            .clamp_range(1.0..=1_000_000.0)
+            .speed(speed)
+            .ui(ui);
+        let y = DragValue::new(&mut limits.1)
+            .prefix("Y ")
+            .clamp_range(1.0..=1_000_000.0)
+            .speed(speed)
+            .ui(ui);
+        let z = DragValue::new(&mut limits.2)
+            .prefix("Z ")
+            .clamp_range(1.0..=1_000_000.0)
+            .speed(speed)
+            .ui(ui);
+        x.changed() || y.changed() || z.changed()
+    })
+    .inner
+}
+
+fn load_stl() -> Option<Result<StlModel, StlError>> {
+    let path = FileDialog::new().add_filter("STL", &["stl"]).pick_file()?;
+    let path = path.to_str()?;
+    Some(StlModel::load(path))
+}
+
+fn load_stock_snapshot() -> Option<Result<StockSnapshot, SnapshotError>> {
+    let path = FileDialog::new()
+        .add_filter("Stock snapshot", &["stock"])
+        .pick_file()?;
+    let path = path.to_str()?;
+    Some(StockSnapshot::load(path))
+}
+
+fn save_stock_snapshot(snapshot: &StockSnapshot) -> Option<Result<(), SnapshotError>> {
+    let path = FileDialog::new()
+        .add_filter("Stock snapshot", &["stock"])
+        .save_file()?;
+    let path = path.to_str()?;
+    Some(snapshot.save(path))
+}
+
+fn load_fixtures() -> Option<Result<FixtureSet, String>> {
+    let path = FileDialog::new().pick_file()?;
+    let path = path.to_str()?;
+    Some(
+        fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| FixtureSet::from_json(&content)),
+    )
+}
+
+fn path_vertices(
+    g_code: &GCode,
+    machine_setup: &MachineSetup,
+    chord_tolerance: f32,
+) -> Vec<SmallVertex> {
+    g_code
+        .path(machine_setup.initial_state(), chord_tolerance)
+        .into_iter()
+        .map(|p| {
+            let p = machine_setup.axis_transform().apply(p);
+            SmallVertex::new([p.0, p.1, p.2])
+        })
+        .collect()
+}
+
+fn load_tool_library() -> Option<Result<ToolLibrary, String>> {
+    let path = FileDialog::new().pick_file()?;
+    let path = path.to_str()?;
+    Some(
+        fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| ToolLibrary::from_json(&content)),
+    )
+}
+
+fn cutter_shape_name(shape: CutterShape) -> &'static str {
+    match shape {
+        CutterShape::Flat => "flat",
+        CutterShape::Spherical => "spherical",
+        CutterShape::Toroidal { .. } => "bull-nose",
+        CutterShape::Conical { .. } => "V-bit / chamfer",
+    }
 }
 
-fn load_target_height_map() -> Option<TargetHeightMap> {
+fn load_target_height_map() -> Option<Result<TargetHeightMap, String>> {
     let path = FileDialog::new().pick_file()?;
     let path = path.to_str()?;
-    serde_json::from_str(fs::read_to_string(path).ok()?.as_str()).ok()
+    Some(
+        fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| TargetHeightMap::from_json(&content)),
+    )
 }
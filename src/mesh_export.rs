@@ -0,0 +1,530 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use derive_getters::Getters;
+
+use crate::axis_transform::AxisTransform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    AsciiStl,
+    BinaryStl,
+    Obj,
+}
+
+/// Closed triangle mesh of the milled stock.
+#[derive(Debug, Clone, Getters)]
+pub struct Mesh {
+    vertices: Vec<(f32, f32, f32)>,
+    triangles: Vec<[u32; 3]>,
+}
+
+/// Rectangle of grid cells `start.0..end.0` by `start.1..end.1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+/// Builds the mesh on a grid of surface vertices, so that no vertex lies on the edge of
+/// another face.
+struct MeshBuilder {
+    grid: (usize, usize),
+    mesh: Mesh,
+}
+
+impl MeshFormat {
+    pub const ALL: [MeshFormat; 3] = [MeshFormat::AsciiStl, MeshFormat::BinaryStl, MeshFormat::Obj];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MeshFormat::AsciiStl | MeshFormat::BinaryStl => "stl",
+            MeshFormat::Obj => "obj",
+        }
+    }
+}
+
+impl Display for MeshFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshFormat::AsciiStl => write!(f, "STL (ASCII)"),
+            MeshFormat::BinaryStl => write!(f, "STL (binary)"),
+            MeshFormat::Obj => write!(f, "OBJ"),
+        }
+    }
+}
+
+impl Mesh {
+    /// Surface of the stock through the centres of the height map columns, closed by side walls
+    /// and a flat bottom, in scene coordinates (cm). The outermost columns are extended to the
+    /// block edges. With `decimate`, flat parts of the surface share one face.
+    pub fn from_heights(heights: &[Vec<f32>], block_size: (f32, f32, f32), decimate: bool) -> Self {
+        let resolution = (heights.len(), heights[0].len());
+        let bottom = -block_size.1 / 2.0;
+        let positions = |count: usize, size: f32| {
+            (0..count + 2)
+                .map(|i| match i {
+                    0 => -size / 2.0,
+                    i if i == count + 1 => size / 2.0,
+                    i => ((i as f32 - 0.5) / count as f32 - 0.5) * size,
+                })
+                .collect::<Vec<_>>()
+        };
+        let positions = (
+            positions(resolution.0, block_size.0),
+            positions(resolution.1, block_size.2),
+        );
+        let grid = (resolution.0 + 2, resolution.1 + 2);
+        let surface = (0..grid.0)
+            .map(|i| {
+                (0..grid.1)
+                    .map(|k| {
+                        heights[i.clamp(1, resolution.0) - 1][k.clamp(1, resolution.1) - 1]
+                            .max(bottom)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut builder = MeshBuilder {
+            grid,
+            mesh: Mesh {
+                vertices: vec![],
+                triangles: vec![],
+            },
+        };
+        for (i, column) in surface.iter().enumerate() {
+            for (k, height) in column.iter().enumerate() {
+                builder.push_vertex((positions.0[i], *height, positions.1[k]));
+            }
+        }
+
+        let cells = (grid.0 - 1, grid.1 - 1);
+        let flat_height = |i: usize, k: usize| {
+            let height = surface[i][k];
+            [(i + 1, k), (i, k + 1), (i + 1, k + 1)]
+                .iter()
+                .all(|&(i, k)| surface[i][k] == height)
+                .then_some(height.to_bits())
+        };
+        let single_cells = |is_included: &dyn Fn(usize, usize) -> bool| {
+            (0..cells.0)
+                .flat_map(|i| (0..cells.1).map(move |k| (i, k)))
+                .filter(|&(i, k)| is_included(i, k))
+                .map(|(i, k)| Region {
+                    start: (i, k),
+                    end: (i + 1, k + 1),
+                })
+                .collect::<Vec<_>>()
+        };
+        let regions = if decimate {
+            let mut regions = merge_regions(cells, flat_height);
+            regions.extend(single_cells(&|i, k| flat_height(i, k).is_none()));
+            regions
+        } else {
+            single_cells(&|_, _| true)
+        };
+        for region in regions {
+            let border = builder.border(region);
+            let (start, end) = (region.start, region.end);
+            let centre = (
+                (positions.0[start.0] + positions.0[end.0]) / 2.0,
+                surface[start.0][start.1],
+                (positions.1[start.1] + positions.1[end.1]) / 2.0,
+            );
+            builder.add_face(&border, centre, (0.0, 1.0, 0.0));
+        }
+
+        let perimeter = builder.border(Region {
+            start: (0, 0),
+            end: cells,
+        });
+        let lower = perimeter
+            .iter()
+            .map(|&index| {
+                let (x, height, z) = builder.mesh.vertices[index as usize];
+                if height > bottom {
+                    builder.push_vertex((x, bottom, z))
+                } else {
+                    index
+                }
+            })
+            .collect::<Vec<_>>();
+        for j in 0..perimeter.len() {
+            let next = (j + 1) % perimeter.len();
+            builder.add_wall([perimeter[j], perimeter[next], lower[next], lower[j]]);
+        }
+        builder.add_face(&lower, (0.0, bottom, 0.0), (0.0, -1.0, 0.0));
+
+        builder.mesh
+    }
+
+    /// Converts the mesh from scene coordinates to machine coordinates in mm.
+    pub fn to_machine(mut self, axis_transform: &AxisTransform) -> Self {
+        let x = axis_transform.to_machine((1.0, 0.0, 0.0));
+        let y = axis_transform.to_machine((0.0, 1.0, 0.0));
+        let z = axis_transform.to_machine((0.0, 0.0, 1.0));
+        let mirrored = dot(x, cross(y, z)) < 0.0;
+
+        for vertex in self.vertices.iter_mut() {
+            *vertex = axis_transform.to_machine(*vertex);
+        }
+        if mirrored {
+            for triangle in self.triangles.iter_mut() {
+                triangle.swap(1, 2);
+            }
+        }
+        self
+    }
+
+    pub fn save(&self, file_path: &str, format: MeshFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        match format {
+            MeshFormat::AsciiStl => self.write_ascii_stl(&mut writer)?,
+            MeshFormat::BinaryStl => self.write_binary_stl(&mut writer)?,
+            MeshFormat::Obj => self.write_obj(&mut writer)?,
+        }
+        writer.flush()
+    }
+
+    pub fn write_ascii_stl(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "solid stock")?;
+        for triangle in self.triangles.iter() {
+            let [a, b, c] = triangle.map(|index| self.vertices[index as usize]);
+            let normal = normal(a, b, c);
+            writeln!(
+                writer,
+                "facet normal {} {} {}",
+                normal.0, normal.1, normal.2
+            )?;
+            writeln!(writer, "  outer loop")?;
+            for vertex in [a, b, c] {
+                writeln!(writer, "    vertex {} {} {}", vertex.0, vertex.1, vertex.2)?;
+            }
+            writeln!(writer, "  endloop")?;
+            writeln!(writer, "endfacet")?;
+        }
+        writeln!(writer, "endsolid stock")
+    }
+
+    pub fn write_binary_stl(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut header = [0u8; 80];
+        let title = b"milled stock";
+        header[..title.len()].copy_from_slice(title);
+        writer.write_all(&header)?;
+        writer.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+        for triangle in self.triangles.iter() {
+            let [a, b, c] = triangle.map(|index| self.vertices[index as usize]);
+            for point in [normal(a, b, c), a, b, c] {
+                for value in [point.0, point.1, point.2] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            writer.write_all(&0u16.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_obj(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "o stock")?;
+        for vertex in self.vertices.iter() {
+            writeln!(writer, "v {} {} {}", vertex.0, vertex.1, vertex.2)?;
+        }
+        for triangle in self.triangles.iter() {
+            writeln!(
+                writer,
+                "f {} {} {}",
+                triangle[0] + 1,
+                triangle[1] + 1,
+                triangle[2] + 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl MeshBuilder {
+    fn vertex(&self, corner: (usize, usize)) -> u32 {
+        (corner.0 * self.grid.1 + corner.1) as u32
+    }
+
+    fn push_vertex(&mut self, vertex: (f32, f32, f32)) -> u32 {
+        self.mesh.vertices.push(vertex);
+        (self.mesh.vertices.len() - 1) as u32
+    }
+
+    /// Every grid vertex on the border of `region`, in order around it.
+    fn border(&self, region: Region) -> Vec<u32> {
+        let (start, end) = (region.start, region.end);
+        (start.1..end.1)
+            .map(|k| (start.0, k))
+            .chain((start.0..end.0).map(|i| (i, end.1)))
+            .chain((start.1 + 1..=end.1).rev().map(|k| (end.0, k)))
+            .chain((start.0 + 1..=end.0).rev().map(|i| (i, start.1)))
+            .map(|corner| self.vertex(corner))
+            .collect()
+    }
+
+    /// Adds the triangle wound so that its normal points along `facing`.
+    fn add_triangle(&mut self, triangle: [u32; 3], facing: (f32, f32, f32)) {
+        let [a, b, c] = triangle.map(|index| self.mesh.vertices[index as usize]);
+        let triangle = if dot(cross(sub(b, a), sub(c, a)), facing) < 0.0 {
+            [triangle[0], triangle[2], triangle[1]]
+        } else {
+            triangle
+        };
+        self.mesh.triangles.push(triangle);
+    }
+
+    /// Face bounded by `border`, facing up or down. A quad is split in two, larger borders are
+    /// fanned around `centre`.
+    fn add_face(&mut self, border: &[u32], centre: (f32, f32, f32), facing: (f32, f32, f32)) {
+        if border.len() == 4 {
+            self.add_triangle([border[0], border[1], border[2]], facing);
+            self.add_triangle([border[0], border[2], border[3]], facing);
+            return;
+        }
+
+        let centre = self.push_vertex(centre);
+        for (j, &vertex) in border.iter().enumerate() {
+            let next = border[(j + 1) % border.len()];
+            self.add_triangle([centre, vertex, next], facing);
+        }
+    }
+
+    /// Side wall below the surface edge from `corners[0]` to `corners[1]`, down to the bottom
+    /// vertices `corners[2]` and `corners[3]`. Where the surface rests on the bottom, the
+    /// corners coincide and fewer triangles are needed.
+    fn add_wall(&mut self, corners: [u32; 4]) {
+        let mut polygon = corners.to_vec();
+        polygon.dedup();
+        if polygon.len() > 1 && polygon[0] == polygon[polygon.len() - 1] {
+            polygon.pop();
+        }
+        if polygon.len() < 3 {
+            return;
+        }
+
+        let (a, b) = (
+            self.mesh.vertices[corners[0] as usize],
+            self.mesh.vertices[corners[1] as usize],
+        );
+        let facing = ((a.0 + b.0) / 2.0, 0.0, (a.2 + b.2) / 2.0);
+        for j in 1..polygon.len() - 1 {
+            self.add_triangle([polygon[0], polygon[j], polygon[j + 1]], facing);
+        }
+    }
+}
+
+/// Greedily covers the columns with a key by rectangles of equal keys.
+fn merge_regions<K: PartialEq>(
+    resolution: (usize, usize),
+    key: impl Fn(usize, usize) -> Option<K>,
+) -> Vec<Region> {
+    let mut covered = vec![vec![false; resolution.1]; resolution.0];
+    let mut regions = vec![];
+
+    for i in 0..resolution.0 {
+        for k in 0..resolution.1 {
+            if covered[i][k] {
+                continue;
+            }
+            let Some(region_key) = key(i, k) else {
+                continue;
+            };
+            let matches = |i: usize, k: usize, covered: &[Vec<bool>]| {
+                !covered[i][k] && key(i, k).as_ref() == Some(&region_key)
+            };
+
+            let mut end_k = k + 1;
+            while end_k < resolution.1 && matches(i, end_k, &covered) {
+                end_k += 1;
+            }
+            let mut end_i = i + 1;
+            while end_i < resolution.0 && (k..end_k).all(|k| matches(end_i, k, &covered)) {
+                end_i += 1;
+            }
+
+            for row in covered[i..end_i].iter_mut() {
+                row[k..end_k].fill(true);
+            }
+            regions.push(Region {
+                start: (i, k),
+                end: (end_i, end_k),
+            });
+        }
+    }
+
+    regions
+}
+
+fn sub(a: (f32, f32, f32), b: (f32, f32, f32)) -> (f32, f32, f32) {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+
+fn dot(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+fn cross(a: (f32, f32, f32), b: (f32, f32, f32)) -> (f32, f32, f32) {
+    (
+        a.1 * b.2 - a.2 * b.1,
+        a.2 * b.0 - a.0 * b.2,
+        a.0 * b.1 - a.1 * b.0,
+    )
+}
+
+fn normal(a: (f32, f32, f32), b: (f32, f32, f32), c: (f32, f32, f32)) -> (f32, f32, f32) {
+    let normal = cross(sub(b, a), sub(c, a));
+    let length = dot(normal, normal).sqrt();
+    if length == 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+    (normal.0 / length, normal.1 / length, normal.2 / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use super::{cross, dot, sub, Mesh};
+    use crate::axis_transform::AxisTransform;
+
+    const BLOCK_SIZE: (f32, f32, f32) = (4.0, 2.0, 3.0);
+
+    /// Stock with a pocket, a step and columns cut through inside and at a corner.
+    fn heights() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 1.0, 1.0],
+            vec![1.0, 0.0, 0.5],
+            vec![1.0, -1.0, 0.5],
+            vec![1.0, 0.5, -1.0],
+        ]
+    }
+
+    fn volume(mesh: &Mesh) -> f32 {
+        mesh.triangles()
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|index| mesh.vertices()[index as usize]);
+                dot(a, cross(b, c)) / 6.0
+            })
+            .sum()
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn mesh_is_closed_and_consistently_wound(#[case] decimate: bool) {
+        let mesh = Mesh::from_heights(&heights(), BLOCK_SIZE, decimate);
+
+        let mut edges = HashMap::new();
+        for triangle in mesh.triangles() {
+            for j in 0..3 {
+                *edges
+                    .entry((triangle[j], triangle[(j + 1) % 3]))
+                    .or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in edges.iter() {
+            assert_eq!(count, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1));
+        }
+        for triangle in mesh.triangles() {
+            let [a, b, c] = triangle.map(|index| mesh.vertices()[index as usize]);
+            let normal = cross(sub(b, a), sub(c, a));
+            assert!(dot(normal, normal) > 0.0);
+        }
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn mesh_encloses_the_stock_volume(#[case] decimate: bool) {
+        // Profile along x only, which the surface through the column centres reproduces
+        // exactly, with one row cut through to the bottom.
+        let heights = [1.0, 0.0, -1.0, 0.5, 0.5].map(|height| vec![height; 3]);
+        let cell_area = BLOCK_SIZE.0 / 5.0 * BLOCK_SIZE.2 / 3.0;
+        let expected = heights
+            .iter()
+            .flatten()
+            .map(|height| (height + 1.0) * cell_area)
+            .sum::<f32>();
+
+        let mesh = Mesh::from_heights(&heights, BLOCK_SIZE, decimate);
+
+        assert!((volume(&mesh) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn freeform_surface_has_two_triangles_per_column() {
+        let heights = (0..50)
+            .map(|i| (0..40).map(|k| ((i * k) % 7) as f32 / 7.0).collect())
+            .collect::<Vec<Vec<f32>>>();
+
+        let mesh = Mesh::from_heights(&heights, BLOCK_SIZE, false);
+
+        // Surface cells, two triangles per wall segment and one per bottom fan segment.
+        let perimeter = 2 * (51 + 41);
+        assert_eq!(mesh.triangles().len(), 2 * 51 * 41 + 3 * perimeter);
+    }
+
+    #[test]
+    fn flat_regions_are_decimated() {
+        let heights = vec![vec![1.0; 50]; 50];
+
+        let full = Mesh::from_heights(&heights, BLOCK_SIZE, false);
+        let decimated = Mesh::from_heights(&heights, BLOCK_SIZE, true);
+
+        assert!(decimated.triangles().len() * 4 < full.triangles().len());
+        assert!((volume(&decimated) - volume(&full)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mirrored_axes_keep_normals_outwards() {
+        let axis_transform = AxisTransform::new((1, -1.0), (0, 1.0)).unwrap();
+        let mesh = Mesh::from_heights(&heights(), BLOCK_SIZE, true);
+        let scene_volume = volume(&mesh);
+
+        let machine_mesh = mesh.to_machine(&axis_transform);
+
+        assert!((volume(&machine_mesh) - scene_volume * 1000.0).abs() < 1e-1);
+    }
+
+    #[test]
+    fn binary_stl_has_fifty_bytes_per_triangle() {
+        let mesh = Mesh::from_heights(&heights(), BLOCK_SIZE, true);
+        let mut bytes = vec![];
+
+        mesh.write_binary_stl(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 84 + 50 * mesh.triangles().len());
+        assert_eq!(
+            u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize,
+            mesh.triangles().len()
+        );
+    }
+
+    #[test]
+    fn obj_lists_vertices_and_faces() {
+        let mesh = Mesh::from_heights(&heights(), BLOCK_SIZE, true);
+        let mut bytes = vec![];
+
+        mesh.write_obj(&mut bytes).unwrap();
+
+        let content = String::from_utf8(bytes).unwrap();
+        let count = |prefix: &str| {
+            content
+                .lines()
+                .filter(|line| line.starts_with(prefix))
+                .count()
+        };
+        assert_eq!(count("v "), mesh.vertices().len());
+        assert_eq!(count("f "), mesh.triangles().len());
+    }
+}
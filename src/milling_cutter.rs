@@ -1,17 +1,405 @@
-#[derive(Debug, Clone)]
-pub enum MillingCutter {
-    Flat(u8),
-    Spherical(u8),
+use derive_getters::Getters;
+use derive_new::new;
+use serde::{Deserialize, Serialize};
+
+use crate::cutter_profile::{CutterProfile, ToolHolder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CutterShape {
+    Flat,
+    Spherical,
+    Toroidal { corner_radius: f32 },
+    Conical { tip_angle: f32, tip_diameter: f32 },
+}
+
+/// Cutter type named in a `CUTTER` comment, before its dimensions are known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShapeKind {
+    Flat,
+    Spherical,
+    Toroidal,
+    Conical,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters, Serialize, Deserialize, new)]
+pub struct MillingCutter {
+    #[getter(copy)]
+    shape: CutterShape,
+    #[getter(copy)]
+    diameter: f32,
+    #[getter(skip)]
+    cutting_length: Option<f32>,
+    #[getter(skip)]
+    overall_length: Option<f32>,
+    #[getter(skip)]
+    #[new(default)]
+    #[serde(default)]
+    shank_diameter: Option<f32>,
+    #[getter(skip)]
+    #[new(default)]
+    #[serde(default)]
+    holder: Option<ToolHolder>,
 }
 
 impl MillingCutter {
     pub fn parse(file_extension: &str) -> Option<Self> {
-        let size = file_extension[1..].parse::<u8>().ok()?;
+        let shape = match file_extension.chars().next()? {
+            'k' => CutterShape::Spherical,
+            'f' => CutterShape::Flat,
+            _ => return None,
+        };
+        let diameter = file_extension[1..].parse::<f32>().ok()?;
+
+        let cutter = MillingCutter::new(shape, diameter, None, None);
+        cutter.validate().ok()?;
+        Some(cutter)
+    }
+
+    /// Checks that the geometry describes a real tool. Every source of cutters goes through it.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+
+        if !positive(self.diameter) {
+            return Err("diameter must be positive".to_string());
+        }
+        match self.shape {
+            CutterShape::Flat | CutterShape::Spherical => {}
+            CutterShape::Toroidal { corner_radius } => {
+                if !positive(corner_radius) || corner_radius > self.diameter / 2.0 {
+                    return Err(
+                        "corner_radius must be positive and at most half the diameter".to_string(),
+                    );
+                }
+            }
+            CutterShape::Conical {
+                tip_angle,
+                tip_diameter,
+            } => {
+                if !(tip_angle > 0.0 && tip_angle < 180.0) {
+                    return Err("tip_angle must be between 0 and 180".to_string());
+                }
+                if !(0.0..self.diameter).contains(&tip_diameter) {
+                    return Err("tip_diameter must be smaller than the diameter".to_string());
+                }
+            }
+        }
+        for (name, length) in [
+            ("cutting_length", self.cutting_length),
+            ("overall_length", self.overall_length),
+            ("shank_diameter", self.shank_diameter),
+        ] {
+            if length.is_some_and(|length| !positive(length)) {
+                return Err(format!("{} must be positive", name));
+            }
+        }
+        if let Some(holder) = self.holder {
+            if !positive(holder.diameter()) || !positive(holder.length()) {
+                return Err(
+                    "holder needs both a positive holder_diameter and holder_length".to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_shank_diameter(mut self, shank_diameter: f32) -> Self {
+        self.shank_diameter = Some(shank_diameter);
+        self
+    }
+
+    pub fn with_holder(mut self, holder: ToolHolder) -> Self {
+        self.holder = Some(holder);
+        self
+    }
+
+    /// Reads a `CUTTER type=ball diameter=6.35 cutting_length=20 overall_length=50` comment.
+    /// Bull-nose mills take `corner_radius`, V-bits and chamfer mills take `tip_angle` (degrees)
+    /// and an optional `tip_diameter`. `shank_diameter`, `holder_diameter` and `holder_length`
+    /// describe the parts above the flutes.
+    /// Returns `None` when the comment does not describe a cutter.
+    pub fn from_comment(comment: &str) -> Option<Result<Self, String>> {
+        let mut tokens = comment.split_whitespace();
+        if !tokens.next()?.eq_ignore_ascii_case("CUTTER") {
+            return None;
+        }
+
+        let mut shape = None;
+        let mut diameter = None;
+        let mut cutting_length = None;
+        let mut overall_length = None;
+        let mut corner_radius = None;
+        let mut tip_angle = None;
+        let mut tip_diameter = None;
+        let mut shank_diameter = None;
+        let mut holder_diameter = None;
+        let mut holder_length = None;
+
+        for token in tokens {
+            let Some((key, value)) = token.split_once('=') else {
+                return Some(Err(format!("expected key=value, found '{}'", token)));
+            };
+            let key = key.to_ascii_lowercase();
+
+            if key == "type" {
+                shape = match value.to_ascii_lowercase().as_str() {
+                    "flat" => Some(ShapeKind::Flat),
+                    "ball" | "spherical" => Some(ShapeKind::Spherical),
+                    "bullnose" | "torus" | "toroidal" => Some(ShapeKind::Toroidal),
+                    "vbit" | "chamfer" | "conical" => Some(ShapeKind::Conical),
+                    _ => return Some(Err(format!("unknown cutter type '{}'", value))),
+                };
+                continue;
+            }
+
+            let Ok(value) = value.parse::<f32>() else {
+                return Some(Err(format!("invalid value '{}' for {}", value, key)));
+            };
+            match key.as_str() {
+                "diameter" => diameter = Some(value),
+                "cutting_length" => cutting_length = Some(value),
+                "overall_length" => overall_length = Some(value),
+                "corner_radius" => corner_radius = Some(value),
+                "tip_angle" => tip_angle = Some(value),
+                "tip_diameter" => tip_diameter = Some(value),
+                "shank_diameter" => shank_diameter = Some(value),
+                "holder_diameter" => holder_diameter = Some(value),
+                "holder_length" => holder_length = Some(value),
+                _ => return Some(Err(format!("unknown cutter property '{}'", key))),
+            }
+        }
+
+        let Some(shape) = shape else {
+            return Some(Err("missing cutter type".to_string()));
+        };
+        let Some(diameter) = diameter else {
+            return Some(Err("missing cutter diameter".to_string()));
+        };
+
+        let shape = match shape {
+            ShapeKind::Flat => CutterShape::Flat,
+            ShapeKind::Spherical => CutterShape::Spherical,
+            ShapeKind::Toroidal => match corner_radius {
+                Some(corner_radius) => CutterShape::Toroidal { corner_radius },
+                None => return Some(Err("missing corner_radius".to_string())),
+            },
+            ShapeKind::Conical => match tip_angle {
+                Some(tip_angle) => CutterShape::Conical {
+                    tip_angle,
+                    tip_diameter: tip_diameter.unwrap_or(0.0),
+                },
+                None => return Some(Err("missing tip_angle".to_string())),
+            },
+        };
+
+        let mut cutter = MillingCutter::new(shape, diameter, cutting_length, overall_length);
+        if let Some(shank_diameter) = shank_diameter {
+            cutter = cutter.with_shank_diameter(shank_diameter);
+        }
+        match (holder_diameter, holder_length) {
+            (None, None) => {}
+            (Some(holder_diameter), Some(holder_length)) => {
+                cutter = cutter.with_holder(ToolHolder::new(holder_diameter, holder_length));
+            }
+            _ => {
+                return Some(Err(
+                    "holder needs both a positive holder_diameter and holder_length".to_string(),
+                ))
+            }
+        }
+
+        Some(cutter.validate().map(|_| cutter))
+    }
+}
+
+impl CutterProfile for MillingCutter {
+    fn radius(&self) -> f32 {
+        self.diameter / 2.0
+    }
+
+    fn height_at(&self, offset: f32) -> f32 {
+        let radius = self.radius();
+        let offset = offset.min(radius);
 
-        match file_extension.chars().nth(0).unwrap() {
-            'k' => Some(MillingCutter::Spherical(size)),
-            'f' => Some(MillingCutter::Flat(size)),
-            _ => None,
+        match self.shape {
+            CutterShape::Flat => 0.0,
+            CutterShape::Spherical => radius - (radius.powi(2) - offset.powi(2)).max(0.0).sqrt(),
+            CutterShape::Toroidal { corner_radius } => {
+                let corner_offset = (offset - (radius - corner_radius)).max(0.0);
+                corner_radius
+                    - (corner_radius.powi(2) - corner_offset.powi(2))
+                        .max(0.0)
+                        .sqrt()
+            }
+            CutterShape::Conical {
+                tip_angle,
+                tip_diameter,
+            } => {
+                let cone_offset = (offset - tip_diameter / 2.0).max(0.0);
+                cone_offset / (tip_angle.to_radians() / 2.0).tan()
+            }
         }
     }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn non_cutting_radius(&self) -> f32 {
+        match self.shape {
+            CutterShape::Flat => self.radius(),
+            CutterShape::Spherical => 0.0,
+            CutterShape::Toroidal { corner_radius } => (self.radius() - corner_radius).max(0.0),
+            CutterShape::Conical { tip_diameter, .. } => tip_diameter / 2.0,
+        }
+    }
+
+    fn cutting_length(&self) -> Option<f32> {
+        self.cutting_length
+    }
+
+    fn overall_length(&self) -> Option<f32> {
+        self.overall_length
+    }
+
+    fn shank_radius(&self) -> f32 {
+        self.shank_diameter
+            .map_or(self.radius(), |shank_diameter| shank_diameter / 2.0)
+    }
+
+    fn holder(&self) -> Option<ToolHolder> {
+        self.holder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{CutterShape, MillingCutter};
+    use crate::cutter_profile::{CutterProfile, ToolHolder};
+
+    #[rstest]
+    #[case(
+        "k16",
+        Some(MillingCutter::new(CutterShape::Spherical, 16.0, None, None))
+    )]
+    #[case("f10", Some(MillingCutter::new(CutterShape::Flat, 10.0, None, None)))]
+    #[case("f", None)]
+    #[case("f-5", None)]
+    #[case("f0", None)]
+    #[case("finf", None)]
+    #[case("x10", None)]
+    #[case("", None)]
+    fn cutter_is_parsed_from_extension(
+        #[case] extension: &str,
+        #[case] cutter: Option<MillingCutter>,
+    ) {
+        assert_eq!(MillingCutter::parse(extension), cutter);
+    }
+
+    #[test]
+    fn cutter_is_parsed_from_comment() {
+        let cutter = MillingCutter::from_comment(
+            "CUTTER type=ball diameter=6.35 cutting_length=20 overall_length=50",
+        );
+
+        assert_eq!(
+            cutter,
+            Some(Ok(MillingCutter::new(
+                CutterShape::Spherical,
+                6.35,
+                Some(20.0),
+                Some(50.0)
+            )))
+        );
+    }
+
+    #[test]
+    fn shank_and_holder_are_parsed_from_comment() {
+        let cutter = MillingCutter::from_comment(
+            "CUTTER type=flat diameter=6 cutting_length=15 overall_length=40 shank_diameter=8 \
+             holder_diameter=30 holder_length=60",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(cutter.shank_radius(), 4.0);
+        assert_eq!(cutter.holder(), Some(ToolHolder::new(30.0, 60.0)));
+    }
+
+    #[rstest]
+    #[case(
+        "CUTTER type=bullnose diameter=10 corner_radius=2",
+        CutterShape::Toroidal { corner_radius: 2.0 }
+    )]
+    #[case(
+        "CUTTER type=vbit diameter=6 tip_angle=60",
+        CutterShape::Conical { tip_angle: 60.0, tip_diameter: 0.0 }
+    )]
+    #[case(
+        "CUTTER type=chamfer diameter=12 tip_angle=90 tip_diameter=1",
+        CutterShape::Conical { tip_angle: 90.0, tip_diameter: 1.0 }
+    )]
+    fn shaped_cutter_is_parsed_from_comment(#[case] comment: &str, #[case] shape: CutterShape) {
+        let cutter = MillingCutter::from_comment(comment).unwrap().unwrap();
+
+        assert_eq!(cutter.shape(), shape);
+    }
+
+    #[rstest]
+    #[case(CutterShape::Flat, 3.0, 0.0)]
+    #[case(CutterShape::Spherical, 0.0, 0.0)]
+    #[case(CutterShape::Spherical, 5.0, 5.0)]
+    #[case(CutterShape::Toroidal { corner_radius: 2.0 }, 2.5, 0.0)]
+    #[case(CutterShape::Toroidal { corner_radius: 2.0 }, 5.0, 2.0)]
+    #[case(CutterShape::Conical { tip_angle: 90.0, tip_diameter: 0.0 }, 4.0, 4.0)]
+    #[case(CutterShape::Conical { tip_angle: 90.0, tip_diameter: 2.0 }, 0.5, 0.0)]
+    #[case(CutterShape::Conical { tip_angle: 90.0, tip_diameter: 2.0 }, 3.0, 2.0)]
+    fn height_profile_matches_geometry(
+        #[case] shape: CutterShape,
+        #[case] offset: f32,
+        #[case] height: f32,
+    ) {
+        let cutter = MillingCutter::new(shape, 10.0, None, None);
+
+        assert!((cutter.height_at(offset) - height).abs() < 1e-5);
+    }
+
+    #[rstest]
+    #[case(CutterShape::Flat, 5.0)]
+    #[case(CutterShape::Spherical, 0.0)]
+    #[case(CutterShape::Toroidal { corner_radius: 2.0 }, 3.0)]
+    #[case(CutterShape::Toroidal { corner_radius: 5.0 }, 0.0)]
+    #[case(CutterShape::Conical { tip_angle: 90.0, tip_diameter: 1.0 }, 0.5)]
+    fn non_cutting_region_matches_geometry(#[case] shape: CutterShape, #[case] radius: f32) {
+        let cutter = MillingCutter::new(shape, 10.0, None, None);
+
+        assert_eq!(cutter.non_cutting_radius(), radius);
+    }
+
+    #[rstest]
+    #[case("ROUGHING")]
+    #[case("")]
+    fn unrelated_comment_is_ignored(#[case] comment: &str) {
+        assert_eq!(MillingCutter::from_comment(comment), None);
+    }
+
+    #[rstest]
+    #[case("CUTTER diameter=6")]
+    #[case("CUTTER type=flat")]
+    #[case("CUTTER type=drill diameter=6")]
+    #[case("CUTTER type=flat diameter=abc")]
+    #[case("CUTTER type=flat diameter")]
+    #[case("CUTTER type=torus diameter=6 corner_radius=4")]
+    #[case("CUTTER type=vbit diameter=6")]
+    #[case("CUTTER type=vbit diameter=6 tip_angle=180")]
+    #[case("CUTTER type=flat diameter=6 holder_diameter=30")]
+    #[case("CUTTER type=flat diameter=6 shank_diameter=0")]
+    #[case("CUTTER type=flat diameter=-5")]
+    #[case("CUTTER type=ball diameter=inf")]
+    #[case("CUTTER type=flat diameter=NaN")]
+    #[case("CUTTER type=flat diameter=6 overall_length=-1")]
+    fn invalid_cutter_comment_is_rejected(#[case] comment: &str) {
+        assert!(matches!(MillingCutter::from_comment(comment), Some(Err(_))));
+    }
 }
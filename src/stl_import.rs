@@ -0,0 +1,244 @@
+use std::fmt::{self, Display, Formatter};
+
+use derive_getters::Getters;
+
+use crate::axis_transform::AxisTransform;
+
+type Triangle = [(f32, f32, f32); 3];
+
+/// Triangles of an STL part model in machine coordinates (mm).
+#[derive(Debug, Clone, Getters)]
+pub struct StlModel {
+    triangles: Vec<Triangle>,
+}
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(std::io::Error),
+    InvalidNumber(String),
+    IncompleteFacet,
+    Empty,
+}
+
+impl Display for StlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::Io(error) => write!(f, "cannot read file: {}", error),
+            StlError::InvalidNumber(value) => write!(f, "invalid number '{}'", value),
+            StlError::IncompleteFacet => write!(f, "facet without three vertices"),
+            StlError::Empty => write!(f, "model has no triangles"),
+        }
+    }
+}
+
+impl From<std::io::Error> for StlError {
+    fn from(error: std::io::Error) -> Self {
+        StlError::Io(error)
+    }
+}
+
+impl StlModel {
+    pub fn load(file_path: &str) -> Result<Self, StlError> {
+        Self::from_bytes(&std::fs::read(file_path)?)
+    }
+
+    /// Parses a binary or ASCII STL file. A file is binary when its size matches the triangle
+    /// count in its header, as binary headers may also start with `solid`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StlError> {
+        let binary_count = bytes
+            .get(80..84)
+            .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+        let triangles = match binary_count {
+            Some(count) if bytes.len() == 84 + count * 50 => Self::parse_binary(&bytes[84..]),
+            _ => Self::parse_ascii(&String::from_utf8_lossy(bytes))?,
+        };
+
+        if triangles.is_empty() {
+            return Err(StlError::Empty);
+        }
+        Ok(Self { triangles })
+    }
+
+    fn parse_binary(bytes: &[u8]) -> Vec<Triangle> {
+        let value = |facet: &[u8], index: usize| {
+            f32::from_le_bytes(facet[index * 4..index * 4 + 4].try_into().unwrap())
+        };
+        let vertex = |facet: &[u8], index: usize| {
+            (
+                value(facet, index),
+                value(facet, index + 1),
+                value(facet, index + 2),
+            )
+        };
+
+        bytes
+            .chunks_exact(50)
+            .map(|facet| [vertex(facet, 3), vertex(facet, 6), vertex(facet, 9)])
+            .collect()
+    }
+
+    fn parse_ascii(content: &str) -> Result<Vec<Triangle>, StlError> {
+        let mut vertices = vec![];
+        let mut tokens = content.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token != "vertex" {
+                continue;
+            }
+            let mut coordinate = || {
+                let value = tokens.next().ok_or(StlError::IncompleteFacet)?;
+                value
+                    .parse::<f32>()
+                    .map_err(|_| StlError::InvalidNumber(value.to_string()))
+            };
+            vertices.push((coordinate()?, coordinate()?, coordinate()?));
+        }
+
+        if vertices.len() % 3 != 0 {
+            return Err(StlError::IncompleteFacet);
+        }
+        Ok(vertices
+            .chunks_exact(3)
+            .map(|vertices| [vertices[0], vertices[1], vertices[2]])
+            .collect())
+    }
+
+    /// Projects the model from above onto the block columns and returns the highest surface
+    /// over the centre of every column as drawn by `generate_block`, in scene units (cm) and
+    /// indexed by x and then z. Heights are limited to the block, columns the model does not
+    /// cover are at the block bottom.
+    pub fn rasterize(
+        &self,
+        axis_transform: &AxisTransform,
+        block_size: (f32, f32, f32),
+        resolution: (u32, u32, u32),
+    ) -> Vec<Vec<f32>> {
+        let (bottom, top) = (-block_size.1 / 2.0, block_size.1 / 2.0);
+        let cell = (
+            block_size.0 / resolution.0 as f32,
+            block_size.2 / resolution.2 as f32,
+        );
+        let centre = |index: usize, cell: f32, size: f32| (index as f32 + 0.5) * cell - size / 2.0;
+        // Columns whose centre lies between `min` and `max`.
+        let range = |min: f32, max: f32, cell: f32, size: f32, resolution: u32| {
+            let first = ((min + size / 2.0) / cell - 0.5).ceil().max(0.0) as usize;
+            let last = ((max + size / 2.0) / cell - 0.5)
+                .floor()
+                .min(resolution as f32 - 1.0);
+            if last < 0.0 {
+                return 0..0;
+            }
+            first..(last as usize + 1).max(first)
+        };
+        let mut heights = vec![vec![bottom; resolution.2 as usize]; resolution.0 as usize];
+
+        for triangle in self.triangles.iter() {
+            let [a, b, c] = triangle.map(|vertex| axis_transform.to_scene(vertex));
+            let area = (b.0 - a.0) * (c.2 - a.2) - (c.0 - a.0) * (b.2 - a.2);
+            if area.abs() < f32::EPSILON {
+                continue;
+            }
+
+            let epsilon = 1e-4 * cell.0.min(cell.1);
+            let xs = range(
+                a.0.min(b.0).min(c.0) - epsilon,
+                a.0.max(b.0).max(c.0) + epsilon,
+                cell.0,
+                block_size.0,
+                resolution.0,
+            );
+            let zs = range(
+                a.2.min(b.2).min(c.2) - epsilon,
+                a.2.max(b.2).max(c.2) + epsilon,
+                cell.1,
+                block_size.2,
+                resolution.2,
+            );
+            for i in xs {
+                for k in zs.clone() {
+                    let (x, z) = (
+                        centre(i, cell.0, block_size.0),
+                        centre(k, cell.1, block_size.2),
+                    );
+                    let u = ((b.0 - x) * (c.2 - z) - (c.0 - x) * (b.2 - z)) / area;
+                    let v = ((c.0 - x) * (a.2 - z) - (a.0 - x) * (c.2 - z)) / area;
+                    let w = 1.0 - u - v;
+                    if u < -1e-4 || v < -1e-4 || w < -1e-4 {
+                        continue;
+                    }
+                    let height = (u * a.1 + v * b.1 + w * c.1).clamp(bottom, top);
+                    heights[i][k] = heights[i][k].max(height);
+                }
+            }
+        }
+
+        heights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{StlError, StlModel};
+    use crate::{axis_transform::AxisTransform, mesh_export::Mesh};
+
+    const BLOCK_SIZE: (f32, f32, f32) = (4.0, 2.0, 4.0);
+    const RESOLUTION: (u32, u32, u32) = (4, 10, 4);
+
+    fn stepped_heights() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 1.0, 1.0, 1.0],
+            vec![1.0, 0.5, 0.5, 1.0],
+            vec![1.0, 0.5, -0.5, 1.0],
+            vec![1.0, 1.0, 1.0, 1.0],
+        ]
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    fn exported_stock_is_imported_back(#[case] binary: bool) {
+        let axis_transform = AxisTransform::default();
+        let mesh =
+            Mesh::from_heights(&stepped_heights(), BLOCK_SIZE, true).to_machine(&axis_transform);
+        let mut bytes = vec![];
+        if binary {
+            mesh.write_binary_stl(&mut bytes).unwrap();
+        } else {
+            mesh.write_ascii_stl(&mut bytes).unwrap();
+        }
+
+        let model = StlModel::from_bytes(&bytes).unwrap();
+        let heights = model.rasterize(&axis_transform, BLOCK_SIZE, RESOLUTION);
+
+        assert_eq!(model.triangles().len(), mesh.triangles().len());
+        for (row, expected_row) in heights.iter().zip(stepped_heights()) {
+            for (height, expected) in row.iter().zip(expected_row) {
+                assert!((height - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn model_outside_the_block_leaves_the_bottom() {
+        let model = StlModel::from_bytes(
+            b"solid t\nfacet normal 0 0 1\nouter loop\nvertex 100 100 5\nvertex 200 100 5\nvertex 100 200 5\nendloop\nendfacet\nendsolid t\n",
+        )
+        .unwrap();
+
+        let heights = model.rasterize(&AxisTransform::default(), BLOCK_SIZE, RESOLUTION);
+
+        assert!(heights.iter().flatten().all(|height| *height == -1.0));
+    }
+
+    #[rstest]
+    #[case(&b"solid t\nvertex 1 2 x\n"[..])]
+    #[case(&b"solid t\nvertex 1 2 3\nvertex 1 2 3\n"[..])]
+    #[case(&b"solid t\nendsolid t\n"[..])]
+    fn invalid_files_are_rejected(#[case] bytes: &[u8]) {
+        assert!(matches!(
+            StlModel::from_bytes(bytes),
+            Err(StlError::InvalidNumber(_) | StlError::IncompleteFacet | StlError::Empty)
+        ));
+    }
+}
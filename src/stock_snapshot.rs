@@ -0,0 +1,376 @@
+use std::fmt::{self, Display, Formatter};
+
+use derive_getters::Getters;
+use derive_new::new;
+
+use crate::g_code::GCode;
+
+const MAGIC: &[u8; 4] = b"MSHM";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 53;
+
+/// Simulated stock saved to a file, so that a simulation can be resumed or shared. Heights are
+/// the height map columns in scene units, indexed by x and then z.
+#[derive(Debug, Clone, PartialEq, Getters, new)]
+pub struct StockSnapshot {
+    /// Block size in cm.
+    #[getter(copy)]
+    block_size: (f32, f32, f32),
+    #[getter(copy)]
+    resolution: (u32, u32, u32),
+    /// O number of the program that cut the stock, if it had one.
+    #[getter(copy)]
+    program_number: Option<u32>,
+    /// [`GCode::fingerprint`] of the program that cut the stock.
+    #[getter(copy)]
+    program_fingerprint: u64,
+    /// Number of instructions of the program already applied to the stock.
+    #[getter(copy)]
+    instruction: usize,
+    heights: Vec<Vec<f32>>,
+}
+
+/// Where the next program continues on a loaded stock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Getters)]
+pub struct ResumePoint {
+    #[getter(copy)]
+    program_number: Option<u32>,
+    #[getter(copy)]
+    program_fingerprint: u64,
+    #[getter(copy)]
+    instruction: usize,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    Truncated,
+    InvalidResolution((u32, u32, u32)),
+    InvalidBlockSize((f32, f32, f32)),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "cannot access file: {}", error),
+            SnapshotError::NotASnapshot => write!(f, "file is not a stock snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::InvalidResolution(resolution) => write!(
+                f,
+                "invalid resolution {}x{}x{}",
+                resolution.0, resolution.1, resolution.2
+            ),
+            SnapshotError::InvalidBlockSize(size) => {
+                write!(f, "invalid block size {}x{}x{}", size.0, size.1, size.2)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+/// Reads little endian values from the front of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        if self.bytes.len() < N {
+            return Err(SnapshotError::Truncated);
+        }
+        let (value, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(value.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+}
+
+impl StockSnapshot {
+    pub fn save(&self, file_path: &str) -> Result<(), SnapshotError> {
+        std::fs::write(file_path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&std::fs::read(file_path)?)
+    }
+
+    /// Encodes the snapshot as a header followed by the heights as little endian `f32`s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(HEADER_SIZE + self.heights.len() * self.resolution.2 as usize * 4);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        for size in [self.block_size.0, self.block_size.1, self.block_size.2] {
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        for resolution in [self.resolution.0, self.resolution.1, self.resolution.2] {
+            bytes.extend_from_slice(&resolution.to_le_bytes());
+        }
+        bytes.push(self.program_number.is_some() as u8);
+        bytes.extend_from_slice(&self.program_number.unwrap_or(0).to_le_bytes());
+        bytes.extend_from_slice(&self.program_fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&(self.instruction as u64).to_le_bytes());
+        for height in self.heights.iter().flatten() {
+            bytes.extend_from_slice(&height.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes };
+        if &reader.take::<4>()? != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let block_size = (reader.f32()?, reader.f32()?, reader.f32()?);
+        if [block_size.0, block_size.1, block_size.2]
+            .iter()
+            .any(|size| !size.is_finite() || *size <= 0.0)
+        {
+            return Err(SnapshotError::InvalidBlockSize(block_size));
+        }
+        let resolution = (reader.u32()?, reader.u32()?, reader.u32()?);
+        if resolution.0 == 0 || resolution.1 == 0 || resolution.2 == 0 {
+            return Err(SnapshotError::InvalidResolution(resolution));
+        }
+        let has_program_number = reader.take::<1>()?[0] != 0;
+        let program_number = reader.u32()?;
+        let program_fingerprint = reader.u64()?;
+        let instruction = reader.u64()? as usize;
+
+        let data_size = (resolution.0 as usize)
+            .checked_mul(resolution.2 as usize)
+            .and_then(|column_count| column_count.checked_mul(4))
+            .ok_or(SnapshotError::InvalidResolution(resolution))?;
+        if reader.bytes.len() != data_size {
+            return Err(SnapshotError::Truncated);
+        }
+        let heights = (0..resolution.0)
+            .map(|_| {
+                (0..resolution.2)
+                    .map(|_| reader.f32())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            block_size,
+            resolution,
+            program_number: has_program_number.then_some(program_number),
+            program_fingerprint,
+            instruction,
+            heights,
+        })
+    }
+
+    pub fn resume_point(&self) -> ResumePoint {
+        ResumePoint {
+            program_number: self.program_number,
+            program_fingerprint: self.program_fingerprint,
+            instruction: self.instruction,
+        }
+    }
+}
+
+impl ResumePoint {
+    /// Instruction to continue `g_code` from. Fails if the stock was cut by another program or
+    /// the instruction is past the end of the program. A stock saved before any instruction was
+    /// applied fits every program.
+    pub fn instruction_for(&self, g_code: &GCode) -> Result<usize, String> {
+        if self.instruction == 0 {
+            return Ok(0);
+        }
+        if g_code.program_number() != self.program_number {
+            return Err(format!(
+                "the stock was cut by {}, not by {}",
+                program_name(self.program_number),
+                program_name(g_code.program_number())
+            ));
+        }
+        if g_code.fingerprint() != self.program_fingerprint {
+            return Err(match self.program_number {
+                Some(number) => format!("the stock was cut by another version of O{}", number),
+                None => "the stock was cut by another program without number".to_string(),
+            });
+        }
+        if self.instruction > g_code.instructions().len() {
+            return Err(format!(
+                "the stock was cut up to instruction {}, but the program has only {}",
+                self.instruction,
+                g_code.instructions().len()
+            ));
+        }
+        Ok(self.instruction)
+    }
+}
+
+pub fn program_name(program_number: Option<u32>) -> String {
+    program_number.map_or("a program without number".to_string(), |number| {
+        format!("O{}", number)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{SnapshotError, StockSnapshot, HEADER_SIZE};
+    use crate::{
+        g_code::GCode,
+        milling_cutter::{CutterShape, MillingCutter},
+    };
+
+    fn program(content: &str) -> GCode {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, None, None);
+        GCode::parse(content, None, Some(cutter), false, None).unwrap()
+    }
+
+    fn snapshot(program_number: Option<u32>) -> StockSnapshot {
+        StockSnapshot::new(
+            (15.0, 5.0, 10.0),
+            (2, 10, 3),
+            program_number,
+            0x0123_4567_89ab_cdef,
+            42,
+            vec![vec![2.5, 2.0, -1.25], vec![0.5, 2.5, 2.5]],
+        )
+    }
+
+    fn cut_by(g_code: &GCode, instruction: usize) -> StockSnapshot {
+        StockSnapshot::new(
+            (15.0, 5.0, 10.0),
+            (1, 10, 1),
+            g_code.program_number(),
+            g_code.fingerprint(),
+            instruction,
+            vec![vec![2.5]],
+        )
+    }
+
+    #[rstest]
+    #[case(Some(7))]
+    #[case(None)]
+    fn snapshot_round_trips(#[case] program_number: Option<u32>) {
+        let snapshot = snapshot(program_number);
+
+        let bytes = snapshot.to_bytes();
+
+        assert_eq!(bytes.len(), HEADER_SIZE + 6 * 4);
+        assert_eq!(StockSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let result = StockSnapshot::from_bytes(b"solid cube\n");
+
+        assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+    }
+
+    #[test]
+    fn truncated_snapshot_is_rejected() {
+        let bytes = snapshot(None).to_bytes();
+
+        let result = StockSnapshot::from_bytes(&bytes[..bytes.len() - 1]);
+
+        assert!(matches!(result, Err(SnapshotError::Truncated)));
+    }
+
+    #[test]
+    fn oversized_resolution_is_rejected() {
+        let mut bytes = snapshot(None).to_bytes();
+        bytes[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let result = StockSnapshot::from_bytes(&bytes);
+
+        assert!(matches!(result, Err(SnapshotError::InvalidResolution(_))));
+    }
+
+    #[rstest]
+    #[case(f32::NAN)]
+    #[case(f32::INFINITY)]
+    #[case(0.0)]
+    #[case(-5.0)]
+    fn invalid_block_size_is_rejected(#[case] size: f32) {
+        let mut bytes = snapshot(None).to_bytes();
+        bytes[12..16].copy_from_slice(&size.to_le_bytes());
+
+        let result = StockSnapshot::from_bytes(&bytes);
+
+        assert!(matches!(result, Err(SnapshotError::InvalidBlockSize(_))));
+    }
+
+    #[rstest]
+    #[case("O7\nN1G00Z50\nN2G00Z40\n", Ok(2))]
+    #[case(
+        "O8\nN1G00Z50\nN2G00Z40\n",
+        Err("the stock was cut by O7, not by O8".to_string())
+    )]
+    #[case(
+        "N1G00Z50\nN2G00Z40\n",
+        Err("the stock was cut by O7, not by a program without number".to_string())
+    )]
+    #[case(
+        "O7\nN1G00Z50\nN2G00Z30\n",
+        Err("the stock was cut by another version of O7".to_string())
+    )]
+    fn resume_needs_the_same_program(
+        #[case] content: &str,
+        #[case] expected: Result<usize, String>,
+    ) {
+        let resume_point = cut_by(&program("O7\nN1G00Z50\nN2G00Z40\n"), 2).resume_point();
+
+        assert_eq!(resume_point.instruction_for(&program(content)), expected);
+    }
+
+    #[test]
+    fn programs_without_number_are_told_apart() {
+        let resume_point = cut_by(&program("N1G00Z50\nN2G00Z40\n"), 1).resume_point();
+
+        assert_eq!(
+            resume_point.instruction_for(&program("N1G00Z50\nN2G00Z30\n")),
+            Err("the stock was cut by another program without number".to_string())
+        );
+        assert_eq!(
+            resume_point.instruction_for(&program("N1G00Z50\nN2G00Z40\n")),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn instruction_past_the_end_is_rejected() {
+        let g_code = program("N1G00Z50\nN2G00Z40\n");
+        let resume_point = cut_by(&g_code, 3).resume_point();
+
+        assert_eq!(
+            resume_point.instruction_for(&g_code),
+            Err("the stock was cut up to instruction 3, but the program has only 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use derive_new::new;
+
+use crate::cutter_profile::CutterProfile;
+
+const GOLDEN_SECTION_ITERATIONS: usize = 32;
+/// Samples taken along the part of a move that passes a column before refining the lowest one,
+/// for profiles that are not convex.
+const PROFILE_SAMPLES: usize = 16;
+
+/// Height-map columns in scene units (cm). Column `(i, k)` samples the point
+/// `((i - resolution.0 / 2) * cell.0, (k - resolution.1 / 2) * cell.1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct ColumnGrid {
+    resolution: (u32, u32),
+    cell: (f32, f32),
+}
+
+impl ColumnGrid {
+    pub fn new(resolution: (u32, u32), size: (f32, f32)) -> Self {
+        Self {
+            resolution,
+            cell: (size.0 / resolution.0 as f32, size.1 / resolution.1 as f32),
+        }
+    }
+
+    pub fn column_position(&self, index: (usize, usize)) -> (f32, f32) {
+        (
+            (index.0 as i32 - self.resolution.0 as i32 / 2) as f32 * self.cell.0,
+            (index.1 as i32 - self.resolution.1 as i32 / 2) as f32 * self.cell.1,
+        )
+    }
+
+    pub fn cell(&self) -> (f32, f32) {
+        self.cell
+    }
+
+    fn index_range(&self, min: f32, max: f32, axis: usize) -> std::ops::Range<usize> {
+        let (resolution, cell) = match axis {
+            0 => (self.resolution.0, self.cell.0),
+            _ => (self.resolution.1, self.cell.1),
+        };
+        let half = (resolution / 2) as f32;
+        let first = ((min / cell).ceil() + half).max(0.0) as usize;
+        let last = ((max / cell).floor() + half + 1.0).clamp(0.0, resolution as f32) as usize;
+        first..last.max(first)
+    }
+}
+
+/// Flat-bottomed cylinder starting `bottom` mm above the tool tip, used to sweep the parts of
+/// the tool that do not cut.
+#[derive(Debug, Clone, Copy, new)]
+pub struct Cylinder {
+    radius: f32,
+    bottom: f32,
+}
+
+impl CutterProfile for Cylinder {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn height_at(&self, _offset: f32) -> f32 {
+        self.bottom
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    fn non_cutting_radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn cutting_length(&self) -> Option<f32> {
+        None
+    }
+
+    fn overall_length(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Visits every column touched by the cutter moving in a straight line from `start` to `end`
+/// (scene coordinates in cm, y pointing up, tool tip at the given position).
+///
+/// `visit` receives the column index, the lowest height of the swept cutter surface above
+/// that column and the horizontal distance from the tool axis at which it is reached.
+/// The minimum is exact for convex profiles. Other profiles are sampled first, so features
+/// narrower than a sixteenth of the cutter diameter may be missed.
+pub fn sweep(
+    start: (f32, f32, f32),
+    end: (f32, f32, f32),
+    cutter_profile: &dyn CutterProfile,
+    grid: &ColumnGrid,
+    mut visit: impl FnMut((usize, usize), f32, f32),
+) {
+    let radius = cutter_profile.radius() / 10.0;
+    let xs = grid.index_range(start.0.min(end.0) - radius, start.0.max(end.0) + radius, 0);
+    let zs = grid.index_range(start.2.min(end.2) - radius, start.2.max(end.2) + radius, 1);
+
+    for x in xs {
+        for z in zs.clone() {
+            let column = grid.column_position((x, z));
+            if let Some((height, distance)) = envelope(start, end, cutter_profile, column) {
+                visit((x, z), height, distance);
+            }
+        }
+    }
+}
+
+fn envelope(
+    start: (f32, f32, f32),
+    end: (f32, f32, f32),
+    cutter_profile: &dyn CutterProfile,
+    column: (f32, f32),
+) -> Option<(f32, f32)> {
+    let radius = cutter_profile.radius() / 10.0;
+    let height_at = |distance: f32| cutter_profile.height_at(distance * 10.0) / 10.0;
+
+    let direction = (end.0 - start.0, end.2 - start.2);
+    let length = (direction.0.powi(2) + direction.1.powi(2)).sqrt();
+    let offset = (column.0 - start.0, column.1 - start.2);
+
+    if length <= f32::EPSILON {
+        let distance = (offset.0.powi(2) + offset.1.powi(2)).sqrt();
+        return (distance <= radius).then(|| (start.1.min(end.1) + height_at(distance), distance));
+    }
+
+    let along = (offset.0 * direction.0 + offset.1 * direction.1) / length;
+    let across_squared = ((offset.0 * direction.1 - offset.1 * direction.0) / length).powi(2);
+    if across_squared > radius.powi(2) {
+        return None;
+    }
+
+    let reach = (radius.powi(2) - across_squared).sqrt();
+    let t_begin = ((along - reach) / length).max(0.0);
+    let t_end = ((along + reach) / length).min(1.0);
+    if t_begin > t_end {
+        return None;
+    }
+
+    let distance_at = |t: f32| ((along - t * length).powi(2) + across_squared).sqrt();
+    let surface_at = |t: f32| start.1 + t * (end.1 - start.1) + height_at(distance_at(t));
+
+    let mut candidates = vec![t_begin, t_end];
+    let (mut low, mut high) = (t_begin, t_end);
+    if !cutter_profile.is_convex() {
+        let step = (t_end - t_begin) / PROFILE_SAMPLES as f32;
+        let sample = |index: usize| t_begin + index as f32 * step;
+        let best = (0..=PROFILE_SAMPLES)
+            .min_by(|&a, &b| surface_at(sample(a)).total_cmp(&surface_at(sample(b))))
+            .unwrap();
+        candidates.push(sample(best));
+        (low, high) = (
+            sample(best.saturating_sub(1)),
+            sample((best + 1).min(PROFILE_SAMPLES)),
+        );
+    }
+
+    let ratio = (5.0f32.sqrt() - 1.0) / 2.0;
+    for _ in 0..GOLDEN_SECTION_ITERATIONS {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if surface_at(left) <= surface_at(right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+
+    candidates.push((low + high) / 2.0);
+    candidates
+        .into_iter()
+        .map(|t| (surface_at(t), distance_at(t).min(radius)))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{sweep, ColumnGrid};
+    use crate::{
+        cutter_profile::CutterProfile,
+        milling_cutter::{CutterShape, MillingCutter},
+    };
+
+    /// Engraving tool with a narrow tip below a wide flat shoulder.
+    #[derive(Debug)]
+    struct SteppedProfile;
+
+    impl CutterProfile for SteppedProfile {
+        fn radius(&self) -> f32 {
+            5.0
+        }
+
+        fn height_at(&self, offset: f32) -> f32 {
+            if offset < 0.5 {
+                0.0
+            } else {
+                3.0
+            }
+        }
+
+        fn non_cutting_radius(&self) -> f32 {
+            0.0
+        }
+
+        fn cutting_length(&self) -> Option<f32> {
+            None
+        }
+
+        fn overall_length(&self) -> Option<f32> {
+            None
+        }
+    }
+
+    fn swept(
+        start: (f32, f32, f32),
+        end: (f32, f32, f32),
+        cutter: &dyn CutterProfile,
+    ) -> HashMap<(usize, usize), f32> {
+        let grid = ColumnGrid::new((100, 100), (10.0, 10.0));
+        let mut heights = HashMap::new();
+        sweep(start, end, cutter, &grid, |index, height, _| {
+            heights.insert(index, height);
+        });
+        heights
+    }
+
+    #[test]
+    fn flat_cutter_leaves_flat_slot() {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, None, None);
+
+        let heights = swept((-1.0, 0.5, 0.0), (1.0, 0.5, 0.0), &cutter);
+
+        assert!(heights.values().all(|h| (h - 0.5).abs() < 1e-5));
+        assert!(heights.contains_key(&(35, 50)));
+        assert!(heights.contains_key(&(65, 50)));
+        assert!(heights.contains_key(&(50, 55)));
+        assert!(!heights.contains_key(&(50, 56)));
+        assert!(!heights.contains_key(&(34, 50)));
+    }
+
+    #[test]
+    fn ramp_is_not_staircased() {
+        let cutter = MillingCutter::new(CutterShape::Flat, 2.0, None, None);
+
+        let heights = swept((-2.0, 0.0, 0.0), (2.0, -0.04, 0.0), &cutter);
+
+        for x in 31..=69 {
+            let expected = (((x as f32 - 50.0) * 0.1 + 0.1 + 2.0) / 4.0).clamp(0.0, 1.0) * -0.04;
+            let expected = expected.max(-0.04);
+            let height = heights[&(x, 50)];
+            assert!(
+                (height - expected).abs() < 1e-4,
+                "column {}: {} != {}",
+                x,
+                height,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn ball_cutter_leaves_exact_groove() {
+        let cutter = MillingCutter::new(CutterShape::Spherical, 10.0, None, None);
+
+        let heights = swept((0.0, 0.0, -2.0), (0.0, 0.0, 2.0), &cutter);
+
+        for x in 46..=54 {
+            let d = (x as f32 - 50.0) * 0.1;
+            let expected = 0.5 - (0.25 - d * d).sqrt();
+            assert!((heights[&(x, 50)] - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn descending_ball_cutter_uses_lowest_point_of_envelope() {
+        let cutter = MillingCutter::new(CutterShape::Spherical, 10.0, None, None);
+
+        let heights = swept((0.0, 1.0, 0.0), (1.0, 0.0, 0.0), &cutter);
+
+        assert!((heights[&(60, 50)] - 0.0).abs() < 1e-4);
+        assert!((heights[&(55, 50)] - (1.0 - 0.5f32.sqrt())).abs() < 1e-4);
+        assert!((heights[&(45, 50)] - 1.5).abs() < 1e-4);
+        assert!(!heights.contains_key(&(44, 50)));
+    }
+
+    #[test]
+    fn stepped_profile_finds_narrow_tip() {
+        let heights = swept((-1.0, 0.5, 0.0), (1.0, 0.5, 0.0), &SteppedProfile);
+
+        for x in 35..=65 {
+            let expected = if (40..=60).contains(&x) { 0.5 } else { 0.8 };
+            let height = heights[&(x, 50)];
+            assert!(
+                (height - expected).abs() < 1e-5,
+                "column {}: {} != {}",
+                x,
+                height,
+                expected
+            );
+        }
+    }
+}
@@ -1,21 +1,199 @@
 use derive_getters::Getters;
+use derive_new::new;
 use glium::{glutin::surface::WindowSurface, Display, Rect, Texture2d};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Getters, Serialize, Deserialize)]
+pub const TARGET_HEIGHT_MAP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LengthUnit {
+    #[default]
+    #[serde(rename = "mm")]
+    Millimeters,
+    #[serde(rename = "cm")]
+    Centimeters,
+    #[serde(rename = "in")]
+    Inches,
+}
+
+/// Heights the milled stock should have. Rows run along scene z and columns along scene x, and
+/// heights are measured up from the middle of the block. Maps without extents, like those
+/// written before version 1, are stretched over the whole block.
+#[derive(Debug, Clone, Getters, Serialize, Deserialize)]
 pub struct TargetHeightMap {
+    #[serde(default)]
+    #[getter(copy)]
+    version: u32,
+    /// Unit of the heights, the origin and the extents.
+    #[serde(default)]
+    #[getter(copy)]
+    units: LengthUnit,
+    /// Scene x and z of the outer corner of the first sample.
+    #[serde(default)]
+    #[getter(copy)]
+    origin: (f32, f32),
+    /// Size covered along scene x and z.
+    #[serde(default)]
+    #[getter(copy)]
+    extents: Option<(f32, f32)>,
     heights: Vec<Vec<f32>>,
 }
 
+/// How far in mm the stock may be cut below the target (gouge) or left above it (leftover).
+#[derive(Debug, Clone, Copy, PartialEq, Getters, new, Serialize)]
+pub struct ToleranceBands {
+    #[getter(copy)]
+    gouge: f32,
+    #[getter(copy)]
+    leftover: f32,
+}
+
+impl LengthUnit {
+    pub fn millimeters(&self) -> f32 {
+        match self {
+            LengthUnit::Millimeters => 1.0,
+            LengthUnit::Centimeters => 10.0,
+            LengthUnit::Inches => 25.4,
+        }
+    }
+}
+
 impl Default for TargetHeightMap {
     fn default() -> Self {
         Self {
+            version: TARGET_HEIGHT_MAP_VERSION,
+            units: LengthUnit::Millimeters,
+            origin: (0.0, 0.0),
+            extents: None,
             heights: vec![vec![0.0; 100]; 100],
         }
     }
 }
 
+impl Default for ToleranceBands {
+    fn default() -> Self {
+        Self {
+            gouge: 1.0,
+            leftover: 1.0,
+        }
+    }
+}
+
+impl ToleranceBands {
+    pub fn set_gouge(&mut self, gouge: f32) {
+        self.gouge = gouge;
+    }
+
+    pub fn set_leftover(&mut self, leftover: f32) {
+        self.leftover = leftover;
+    }
+}
+
 impl TargetHeightMap {
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let target: TargetHeightMap =
+            serde_json::from_str(content).map_err(|error| error.to_string())?;
+        if target.version > TARGET_HEIGHT_MAP_VERSION {
+            return Err(format!("unsupported version {}", target.version));
+        }
+        let width = target.heights.first().map_or(0, Vec::len);
+        if width == 0 || target.heights.iter().any(|row| row.len() != width) {
+            return Err("heights must be a non-empty rectangular grid".to_string());
+        }
+        if let Some(extents) = target.extents {
+            if extents.0 <= 0.0 || extents.1 <= 0.0 {
+                return Err("extents must be positive".to_string());
+            }
+        }
+        Ok(target)
+    }
+
+    /// Target covering the block from height map columns in scene units (cm) indexed by x and
+    /// then z, as returned by `StlModel::rasterize`.
+    pub fn from_scene_heights(heights: &[Vec<f32>], block_size: (f32, f32, f32)) -> Self {
+        Self {
+            version: TARGET_HEIGHT_MAP_VERSION,
+            units: LengthUnit::Millimeters,
+            origin: (-block_size.0 * 10.0 / 2.0, -block_size.2 * 10.0 / 2.0),
+            extents: Some((block_size.0 * 10.0, block_size.2 * 10.0)),
+            heights: (0..heights[0].len())
+                .map(|k| heights.iter().map(|column| column[k] * 10.0).collect())
+                .collect(),
+        }
+    }
+
+    /// Samples the target at the centre of every block column. The result is in mm with one
+    /// sample per column. Columns outside the extents get NaN, meaning there is no target.
+    pub fn resample(&self, block_size: (f32, f32, f32), resolution: (u32, u32, u32)) -> Self {
+        let scale = self.units.millimeters();
+        let normalized = |index: u32, count: u32| index as f32 / (count.max(2) - 1) as f32;
+        let centre =
+            |index: u32, count: u32, size: f32| ((index as f32 + 0.5) / count as f32 - 0.5) * size;
+
+        let heights = (0..resolution.2)
+            .map(|k| {
+                (0..resolution.0)
+                    .map(|i| match self.extents {
+                        None => {
+                            self.height_at(normalized(i, resolution.0), normalized(k, resolution.2))
+                                * scale
+                        }
+                        Some(extents) => {
+                            let x = centre(i, resolution.0, block_size.0 * 10.0) / scale;
+                            let z = centre(k, resolution.2, block_size.2 * 10.0) / scale;
+                            let u = (x - self.origin.0) / extents.0;
+                            let v = (z - self.origin.1) / extents.1;
+                            if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+                                return f32::NAN;
+                            }
+                            self.interpolate(u, v) * scale
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            version: TARGET_HEIGHT_MAP_VERSION,
+            units: LengthUnit::Millimeters,
+            origin: (-block_size.0 * 10.0 / 2.0, -block_size.2 * 10.0 / 2.0),
+            extents: Some((block_size.0 * 10.0, block_size.2 * 10.0)),
+            heights,
+        }
+    }
+
+    /// Whether the map has one sample per column of the block, as returned by `resample`.
+    pub fn matches_block(&self, block_size: (f32, f32, f32), resolution: (u32, u32, u32)) -> bool {
+        self.units == LengthUnit::Millimeters
+            && self.extents == Some((block_size.0 * 10.0, block_size.2 * 10.0))
+            && self.heights.len() == resolution.2 as usize
+            && self.heights[0].len() == resolution.0 as usize
+    }
+
+    /// Height at texture coordinates `u` (along x) and `v` (along z) from 0 to 1, picking the
+    /// nearest sample like the block shader.
+    pub fn height_at(&self, u: f32, v: f32) -> f32 {
+        let texel = |coordinate: f32, count: usize| {
+            ((coordinate * count as f32).floor().max(0.0) as usize).min(count - 1)
+        };
+        let row = &self.heights[texel(v, self.heights.len())];
+        row[texel(u, row.len())]
+    }
+
+    /// Bilinear interpolation between the sample centres, clamped at the border.
+    fn interpolate(&self, u: f32, v: f32) -> f32 {
+        let neighbours = |coordinate: f32, count: usize| {
+            let position = (coordinate * count as f32 - 0.5).clamp(0.0, (count - 1) as f32);
+            let first = position.floor() as usize;
+            (first, (first + 1).min(count - 1), position - first as f32)
+        };
+        let (row, next_row, t) = neighbours(v, self.heights.len());
+        let (column, next_column, s) = neighbours(u, self.heights[0].len());
+        let along_row =
+            |row: usize| self.heights[row][column] * (1.0 - s) + self.heights[row][next_column] * s;
+        along_row(row) * (1.0 - t) + along_row(next_row) * t
+    }
+
     pub fn to_texture(&self, display: &Display<WindowSurface>) -> Texture2d {
         let texture = Texture2d::empty_with_format(
             display,
@@ -26,6 +204,7 @@ impl TargetHeightMap {
         )
         .unwrap();
 
+        let scale = self.units.millimeters() / 10.0;
         texture.write(
             Rect {
                 left: 0,
@@ -35,10 +214,76 @@ impl TargetHeightMap {
             },
             self.heights
                 .iter()
-                .map(|x| x.iter().map(|y| y / 10.0).collect())
+                .map(|x| x.iter().map(|y| y * scale).collect())
                 .collect::<Vec<Vec<_>>>(),
         );
 
         texture
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::TargetHeightMap;
+
+    #[test]
+    fn scene_heights_are_transposed_to_millimetres() {
+        let target = TargetHeightMap::from_scene_heights(
+            &[vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]],
+            (2.0, 2.0, 3.0),
+        );
+
+        assert_eq!(
+            target.heights(),
+            &vec![vec![10.0, 40.0], vec![20.0, 50.0], vec![30.0, 60.0]]
+        );
+        assert_eq!(target.origin(), (-10.0, -15.0));
+        assert_eq!(target.extents(), Some((20.0, 30.0)));
+    }
+
+    #[test]
+    fn legacy_map_is_stretched_over_the_block() {
+        let target =
+            TargetHeightMap::from_json(r#"{ "heights": [[1.0, 2.0], [3.0, 4.0]] }"#).unwrap();
+
+        let resampled = target.resample((10.0, 5.0, 10.0), (4, 10, 4));
+
+        assert_eq!(target.version(), 0);
+        assert_eq!(resampled.heights()[0], vec![1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(resampled.heights()[3], vec![3.0, 3.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn map_with_extents_is_placed_and_scaled() {
+        // A 2 cm wide ramp rising along x over the middle of a 4 cm block.
+        let target = TargetHeightMap::from_json(
+            r#"{
+                "version": 1,
+                "units": "cm",
+                "origin": [-1.0, -2.0],
+                "extents": [2.0, 4.0],
+                "heights": [[0.0, 1.0]]
+            }"#,
+        )
+        .unwrap();
+
+        let resampled = target.resample((4.0, 2.0, 4.0), (4, 10, 1));
+
+        let row = &resampled.heights()[0];
+        assert!(row[0].is_nan());
+        assert!((row[1] - 0.0).abs() < 1e-5);
+        assert!((row[2] - 10.0).abs() < 1e-5);
+        assert!(row[3].is_nan());
+    }
+
+    #[rstest]
+    #[case(r#"{ "version": 2, "heights": [[0.0]] }"#)]
+    #[case(r#"{ "heights": [] }"#)]
+    #[case(r#"{ "heights": [[0.0, 1.0], [0.0]] }"#)]
+    #[case(r#"{ "extents": [0.0, 1.0], "heights": [[0.0]] }"#)]
+    fn invalid_maps_are_rejected(#[case] content: &str) {
+        assert!(TargetHeightMap::from_json(content).is_err());
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+use derive_getters::Getters;
+use derive_new::new;
+use serde::Serialize;
+
+use crate::{g_code::GCode, g_code_instruction::MotionMode, machine_state::MachineState};
+
+const BISECTION_ITERATIONS: usize = 32;
+
+/// Dynamic limits of the machine axes used to estimate cycle time. All limits are positive.
+#[derive(Debug, Clone, Copy, PartialEq, Getters)]
+pub struct MotionLimits {
+    /// Maximum X, Y and Z acceleration in mm/s².
+    #[getter(copy)]
+    acceleration: (f32, f32, f32),
+    /// Maximum X, Y and Z jerk in mm/s³.
+    #[getter(copy)]
+    jerk: (f32, f32, f32),
+    /// How far in mm the tool may cut a sharp corner short. Sets how fast corners are taken.
+    #[getter(copy)]
+    junction_deviation: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum MoveKind {
+    Rapid,
+    Linear,
+    Arc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Getters, new, Serialize)]
+pub struct MoveTime {
+    /// Index of the instruction in the program.
+    #[getter(copy)]
+    instruction: usize,
+    #[getter(copy)]
+    kind: MoveKind,
+    #[getter(copy)]
+    tool: Option<u32>,
+    /// Path length in mm.
+    #[getter(copy)]
+    length: f32,
+    /// Machine time in seconds.
+    #[getter(copy)]
+    time: f32,
+}
+
+/// Totals of a group of moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Getters, Serialize)]
+pub struct Breakdown {
+    #[getter(copy)]
+    moves: usize,
+    #[getter(copy)]
+    length: f32,
+    #[getter(copy)]
+    time: f32,
+}
+
+/// Machine time of every move of a program, including acceleration and slowing down in
+/// corners.
+#[derive(Debug, Clone, Getters)]
+pub struct TimeEstimate {
+    moves: Vec<MoveTime>,
+}
+
+#[derive(Serialize)]
+struct ToolBreakdown {
+    tool: Option<u32>,
+    #[serde(flatten)]
+    breakdown: Breakdown,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    total_time: f32,
+    by_kind: BTreeMap<MoveKind, Breakdown>,
+    by_tool: Vec<ToolBreakdown>,
+    moves: &'a [MoveTime],
+}
+
+/// Straight piece of the tool path with its speed and acceleration limits along the path.
+struct Segment {
+    move_index: usize,
+    length: f32,
+    direction: (f32, f32, f32),
+    /// Programmed speed in mm/s.
+    speed: f32,
+    acceleration: f32,
+    jerk: f32,
+    /// The machine comes to a stop before this segment, e.g. after a tool change.
+    stop_before: bool,
+}
+
+impl Default for MotionLimits {
+    fn default() -> Self {
+        Self {
+            acceleration: (500.0, 500.0, 300.0),
+            jerk: (10000.0, 10000.0, 5000.0),
+            junction_deviation: 0.02,
+        }
+    }
+}
+
+impl MotionLimits {
+    pub fn new(
+        acceleration: (f32, f32, f32),
+        jerk: (f32, f32, f32),
+        junction_deviation: f32,
+    ) -> Result<Self, String> {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        let all_positive = |values: (f32, f32, f32)| {
+            positive(values.0) && positive(values.1) && positive(values.2)
+        };
+
+        if !all_positive(acceleration) {
+            return Err("acceleration limits must be positive".to_string());
+        }
+        if !all_positive(jerk) {
+            return Err("jerk limits must be positive".to_string());
+        }
+        if !positive(junction_deviation) {
+            return Err("junction deviation must be positive".to_string());
+        }
+
+        Ok(Self {
+            acceleration,
+            jerk,
+            junction_deviation,
+        })
+    }
+
+    pub fn set_acceleration(&mut self, acceleration: (f32, f32, f32)) -> Result<(), String> {
+        *self = Self::new(acceleration, self.jerk, self.junction_deviation)?;
+        Ok(())
+    }
+
+    pub fn set_jerk(&mut self, jerk: (f32, f32, f32)) -> Result<(), String> {
+        *self = Self::new(self.acceleration, jerk, self.junction_deviation)?;
+        Ok(())
+    }
+
+    pub fn set_junction_deviation(&mut self, junction_deviation: f32) -> Result<(), String> {
+        *self = Self::new(self.acceleration, self.jerk, junction_deviation)?;
+        Ok(())
+    }
+}
+
+impl MoveKind {
+    pub const ALL: [MoveKind; 3] = [MoveKind::Rapid, MoveKind::Linear, MoveKind::Arc];
+}
+
+impl Display for MoveKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveKind::Rapid => write!(f, "rapid"),
+            MoveKind::Linear => write!(f, "linear"),
+            MoveKind::Arc => write!(f, "arc"),
+        }
+    }
+}
+
+impl Breakdown {
+    fn add(&mut self, move_time: &MoveTime) {
+        self.moves += 1;
+        self.length += move_time.length;
+        self.time += move_time.time;
+    }
+}
+
+impl TimeEstimate {
+    /// Estimates the machine time of `code` run from `machine_state`. Rapid moves and moves
+    /// without a programmed feed run at `rapid_rate` (mm/min). The machine stops at the start
+    /// and end of the program, on reversals and on tool changes.
+    pub fn new(
+        code: &GCode,
+        mut machine_state: MachineState,
+        chord_tolerance: f32,
+        rapid_rate: f32,
+        limits: &MotionLimits,
+    ) -> Self {
+        let mut moves = vec![];
+        let mut segments = vec![];
+        let mut active_tool = code.tool_number();
+        let mut stop = true;
+
+        for (index, instruction) in code.instructions().iter().enumerate() {
+            let mut previous = machine_state.position();
+            let points = machine_state.apply(instruction, chord_tolerance);
+            if instruction.has_m(6) {
+                active_tool = machine_state.tool().or(active_tool);
+                stop = true;
+            }
+            if points.is_empty() {
+                continue;
+            }
+
+            let kind = if machine_state.last_move_rapid() {
+                MoveKind::Rapid
+            } else {
+                match machine_state.motion_mode() {
+                    MotionMode::Rapid => MoveKind::Rapid,
+                    MotionMode::Linear => MoveKind::Linear,
+                    MotionMode::ClockwiseArc | MotionMode::CounterClockwiseArc => MoveKind::Arc,
+                }
+            };
+            let rate = if kind == MoveKind::Rapid {
+                rapid_rate
+            } else {
+                machine_state
+                    .feed()
+                    .filter(|feed| *feed > 0.0)
+                    .unwrap_or(rapid_rate)
+            };
+
+            let move_index = moves.len();
+            let mut move_length = 0.0;
+            for point in points {
+                let delta = (
+                    point.0 - previous.0,
+                    point.1 - previous.1,
+                    point.2 - previous.2,
+                );
+                previous = point;
+                let length = (delta.0.powi(2) + delta.1.powi(2) + delta.2.powi(2)).sqrt();
+                if length < 1e-6 {
+                    continue;
+                }
+                let direction = (delta.0 / length, delta.1 / length, delta.2 / length);
+                segments.push(Segment {
+                    move_index,
+                    length,
+                    direction,
+                    speed: rate / 60.0,
+                    acceleration: axis_limit(limits.acceleration, direction),
+                    jerk: axis_limit(limits.jerk, direction),
+                    stop_before: stop,
+                });
+                stop = false;
+                move_length += length;
+            }
+            moves.push(MoveTime::new(index, kind, active_tool, move_length, 0.0));
+        }
+
+        let mut entry_speeds = segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                if i == 0 || segment.stop_before {
+                    0.0
+                } else {
+                    junction_speed(&segments[i - 1], segment, limits.junction_deviation)
+                }
+            })
+            .collect::<Vec<_>>();
+        for i in (0..segments.len()).rev() {
+            let exit_speed = entry_speeds.get(i + 1).copied().unwrap_or(0.0);
+            entry_speeds[i] = entry_speeds[i].min(reachable_speed(exit_speed, &segments[i]));
+        }
+        for i in 1..segments.len() {
+            let reachable = reachable_speed(entry_speeds[i - 1], &segments[i - 1]);
+            entry_speeds[i] = entry_speeds[i].min(reachable);
+        }
+
+        for (i, segment) in segments.iter().enumerate() {
+            let exit_speed = entry_speeds.get(i + 1).copied().unwrap_or(0.0);
+            moves[segment.move_index].time += segment_time(entry_speeds[i], exit_speed, segment);
+        }
+
+        Self { moves }
+    }
+
+    pub fn total_time(&self) -> f32 {
+        self.moves.iter().map(|move_time| move_time.time).sum()
+    }
+
+    pub fn by_kind(&self) -> BTreeMap<MoveKind, Breakdown> {
+        let mut breakdown = BTreeMap::<MoveKind, Breakdown>::new();
+        for move_time in self.moves.iter() {
+            breakdown.entry(move_time.kind).or_default().add(move_time);
+        }
+        breakdown
+    }
+
+    pub fn by_tool(&self) -> BTreeMap<Option<u32>, Breakdown> {
+        let mut breakdown = BTreeMap::<Option<u32>, Breakdown>::new();
+        for move_time in self.moves.iter() {
+            breakdown.entry(move_time.tool).or_default().add(move_time);
+        }
+        breakdown
+    }
+
+    /// JSON report with the total time, the breakdowns and every move.
+    pub fn to_json(&self) -> String {
+        let report = Report {
+            total_time: self.total_time(),
+            by_kind: self.by_kind(),
+            by_tool: self
+                .by_tool()
+                .into_iter()
+                .map(|(tool, breakdown)| ToolBreakdown { tool, breakdown })
+                .collect(),
+            moves: &self.moves,
+        };
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+}
+
+/// Limit along `direction` given per axis limits, so that no axis exceeds its own.
+fn axis_limit(limits: (f32, f32, f32), direction: (f32, f32, f32)) -> f32 {
+    [
+        (limits.0, direction.0),
+        (limits.1, direction.1),
+        (limits.2, direction.2),
+    ]
+    .into_iter()
+    .filter(|(_, component)| component.abs() > 1e-6)
+    .map(|(limit, component)| limit / component.abs())
+    .fold(f32::INFINITY, f32::min)
+}
+
+/// Highest speed at which the tool can pass from `previous` to `next` without leaving the
+/// path by more than `deviation` (the junction deviation model).
+fn junction_speed(previous: &Segment, next: &Segment, deviation: f32) -> f32 {
+    let max_speed = previous.speed.min(next.speed);
+    let cos = -(previous.direction.0 * next.direction.0
+        + previous.direction.1 * next.direction.1
+        + previous.direction.2 * next.direction.2);
+    if cos > 0.999999 {
+        return 0.0;
+    }
+    if cos < -0.999999 {
+        return max_speed;
+    }
+
+    let sin_half = (0.5 * (1.0 - cos)).sqrt();
+    let acceleration = previous.acceleration.min(next.acceleration);
+    (acceleration * deviation * sin_half / (1.0 - sin_half))
+        .sqrt()
+        .min(max_speed)
+}
+
+/// Time in seconds of a jerk limited change of speed by `speed_change`.
+fn ramp_time(speed_change: f32, segment: &Segment) -> f32 {
+    let (acceleration, jerk) = (segment.acceleration, segment.jerk);
+    if speed_change * jerk >= acceleration * acceleration {
+        speed_change / acceleration + acceleration / jerk
+    } else {
+        2.0 * (speed_change / jerk).sqrt()
+    }
+}
+
+/// Distance covered while changing speed from `from` to `to`. The S-curve is symmetric, so the
+/// mean speed is halfway between both.
+fn ramp_distance(from: f32, to: f32, segment: &Segment) -> f32 {
+    (from + to) / 2.0 * ramp_time((to - from).abs(), segment)
+}
+
+/// Highest speed reachable at the other end of `segment` when it is entered at `from`.
+fn reachable_speed(from: f32, segment: &Segment) -> f32 {
+    if ramp_distance(from, segment.speed, segment) <= segment.length {
+        return segment.speed;
+    }
+
+    let (mut low, mut high) = (from, segment.speed);
+    for _ in 0..BISECTION_ITERATIONS {
+        let middle = (low + high) / 2.0;
+        if ramp_distance(from, middle, segment) <= segment.length {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+/// Time in seconds of accelerating from `entry`, cruising and slowing down to `exit` on
+/// `segment`.
+fn segment_time(entry: f32, exit: f32, segment: &Segment) -> f32 {
+    let ramps =
+        |peak: f32| ramp_distance(entry, peak, segment) + ramp_distance(exit, peak, segment);
+    let peak = if ramps(segment.speed) <= segment.length {
+        segment.speed
+    } else {
+        let (mut low, mut high) = (entry.max(exit), segment.speed);
+        for _ in 0..BISECTION_ITERATIONS {
+            let middle = (low + high) / 2.0;
+            if ramps(middle) <= segment.length {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        low
+    };
+
+    let cruise = (segment.length - ramps(peak)).max(0.0);
+    ramp_time(peak - entry, segment) + ramp_time(peak - exit, segment) + cruise / peak.max(1e-6)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{MotionLimits, MoveKind, TimeEstimate};
+    use crate::{
+        g_code::GCode,
+        machine_state::MachineState,
+        milling_cutter::{CutterShape, MillingCutter},
+    };
+
+    fn estimate(program: &str, limits: &MotionLimits) -> TimeEstimate {
+        let cutter = MillingCutter::new(CutterShape::Flat, 10.0, None, None);
+        let g_code = GCode::parse(program, None, Some(cutter), false, None).unwrap();
+        TimeEstimate::new(
+            &g_code,
+            MachineState::new((0.0, 0.0, 0.0)),
+            0.01,
+            6000.0,
+            limits,
+        )
+    }
+
+    fn stiff_limits(acceleration: f32) -> MotionLimits {
+        MotionLimits::new(
+            (acceleration, acceleration, acceleration),
+            (1e9, 1e9, 1e9),
+            0.02,
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case("N1G01X1000F600\n", 100.0)]
+    #[case("N1G00X1000\n", 10.0)]
+    fn long_move_takes_length_over_rate(#[case] program: &str, #[case] expected: f32) {
+        let estimate = estimate(program, &stiff_limits(1e6));
+
+        assert!((estimate.total_time() - expected).abs() < 1e-2);
+    }
+
+    #[test]
+    fn short_move_never_reaches_feed() {
+        // Accelerates over half a millimetre at 100 mm/s² and slows down over the other half.
+        let estimate = estimate("N1G01X1F6000\n", &stiff_limits(100.0));
+
+        assert!((estimate.total_time() - 0.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn jerk_limit_slows_acceleration() {
+        let limits = MotionLimits::new((100.0, 100.0, 100.0), (100.0, 100.0, 100.0), 0.02).unwrap();
+
+        let stiff = estimate("N1G01X100F6000\n", &stiff_limits(100.0));
+        let jerky = estimate("N1G01X100F6000\n", &limits);
+
+        assert!(jerky.total_time() > stiff.total_time() + 0.5);
+    }
+
+    #[rstest]
+    #[case((0.0, 500.0, 300.0), (1e4, 1e4, 1e4), 0.02)]
+    #[case((500.0, 500.0, -1.0), (1e4, 1e4, 1e4), 0.02)]
+    #[case((500.0, 500.0, 300.0), (1e4, 0.0, 1e4), 0.02)]
+    #[case((500.0, 500.0, 300.0), (1e4, f32::NAN, 1e4), 0.02)]
+    #[case((500.0, 500.0, 300.0), (1e4, 1e4, 1e4), 0.0)]
+    fn non_positive_limits_are_rejected(
+        #[case] acceleration: (f32, f32, f32),
+        #[case] jerk: (f32, f32, f32),
+        #[case] junction_deviation: f32,
+    ) {
+        assert!(MotionLimits::new(acceleration, jerk, junction_deviation).is_err());
+    }
+
+    #[test]
+    fn rejected_limit_leaves_limits_unchanged() {
+        let mut limits = MotionLimits::default();
+
+        assert!(limits.set_acceleration((500.0, 0.0, 300.0)).is_err());
+        assert!(limits.set_jerk((1e4, 1e4, -5.0)).is_err());
+
+        assert_eq!(limits, MotionLimits::default());
+    }
+
+    #[test]
+    fn corners_slow_the_tool_down() {
+        let limits = MotionLimits::default();
+
+        let straight = estimate("N1G01X10F3000\nN2G01X20\nN3G01X30\nN4G01X40\n", &limits);
+        let square = estimate("N1G01X10F3000\nN2G01Y10\nN3G01X0\nN4G01Y0\n", &limits);
+
+        assert!(square.total_time() > straight.total_time());
+    }
+
+    #[test]
+    fn time_is_broken_down_by_kind_and_tool() {
+        let estimate = estimate(
+            "N1T1M06\nN2G00X10\nN3G01X20F600\nN4T2M06\nN5G02X30R5\nN6G00Z10\n",
+            &MotionLimits::default(),
+        );
+
+        let by_kind = estimate.by_kind();
+        assert_eq!(by_kind.keys().copied().collect::<Vec<_>>(), MoveKind::ALL);
+        assert_eq!(by_kind[&MoveKind::Rapid].moves(), 2);
+
+        let by_tool = estimate.by_tool();
+        assert_eq!(
+            by_tool.keys().copied().collect::<Vec<_>>(),
+            [Some(1), Some(2)]
+        );
+        assert_eq!(by_tool[&Some(1)].moves(), 2);
+        assert!((by_tool[&Some(1)].length() - 20.0).abs() < 1e-4);
+
+        let total = by_tool
+            .values()
+            .map(|breakdown| breakdown.time())
+            .sum::<f32>();
+        assert!((total - estimate.total_time()).abs() < 1e-4);
+    }
+}
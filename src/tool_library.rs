@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+
+use crate::milling_cutter::MillingCutter;
+
+#[derive(Debug, Clone, Getters, Serialize, Deserialize)]
+pub struct Tool {
+    #[getter(copy)]
+    number: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(flatten)]
+    cutter: MillingCutter,
+    #[getter(copy)]
+    #[serde(default)]
+    max_feed: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Getters, Serialize, Deserialize)]
+pub struct ToolLibrary {
+    tools: Vec<Tool>,
+}
+
+impl ToolLibrary {
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let library: ToolLibrary =
+            serde_json::from_str(content).map_err(|error| error.to_string())?;
+
+        let mut numbers = HashSet::new();
+        for tool in library.tools.iter() {
+            if !numbers.insert(tool.number) {
+                return Err(format!("tool T{} is defined twice", tool.number));
+            }
+            tool.cutter
+                .validate()
+                .map_err(|error| format!("tool T{}: {}", tool.number, error))?;
+        }
+
+        Ok(library)
+    }
+
+    pub fn get(&self, number: u32) -> Option<&Tool> {
+        self.tools.iter().find(|tool| tool.number == number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToolLibrary;
+    use crate::milling_cutter::CutterShape;
+
+    const LIBRARY: &str = r#"{
+        "tools": [
+            {
+                "number": 1,
+                "name": "rougher",
+                "shape": "Flat",
+                "diameter": 16.0,
+                "cutting_length": 30.0,
+                "overall_length": 75.0,
+                "max_feed": 2000.0
+            },
+            {
+                "number": 4,
+                "shape": { "Toroidal": { "corner_radius": 1.0 } },
+                "diameter": 8.0,
+                "cutting_length": 20.0,
+                "overall_length": 60.0
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn tools_are_looked_up_by_number() {
+        let library = ToolLibrary::from_json(LIBRARY).unwrap();
+
+        let rougher = library.get(1).unwrap();
+        assert_eq!(rougher.name(), "rougher");
+        assert_eq!(rougher.cutter().shape(), CutterShape::Flat);
+        assert_eq!(rougher.max_feed(), Some(2000.0));
+
+        let finisher = library.get(4).unwrap();
+        assert_eq!(
+            finisher.cutter().shape(),
+            CutterShape::Toroidal { corner_radius: 1.0 }
+        );
+        assert_eq!(finisher.max_feed(), None);
+
+        assert!(library.get(2).is_none());
+    }
+
+    #[test]
+    fn duplicate_tool_numbers_are_rejected() {
+        let content = r#"{ "tools": [
+            { "number": 1, "shape": "Flat", "diameter": 6.0 },
+            { "number": 1, "shape": "Spherical", "diameter": 6.0 }
+        ] }"#;
+
+        assert!(ToolLibrary::from_json(content).is_err());
+    }
+
+    #[test]
+    fn invalid_cutters_are_rejected() {
+        let content = r#"{ "tools": [{ "number": 1, "shape": "Flat", "diameter": -6.0 }] }"#;
+
+        assert!(ToolLibrary::from_json(content).is_err());
+    }
+}